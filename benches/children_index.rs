@@ -0,0 +1,25 @@
+//! Insertion and search times on skewed data, where the root has most elements as children,
+//! for several child index thresholds
+//!
+//! Run with `cargo bench --bench children_index`.
+use bktree::*;
+use std::time::Instant;
+
+fn main() {
+    let words: Vec<[i64; 1]> = (0..20_000).map(|i| [i]).collect();
+    for threshold in [usize::MAX, DEFAULT_INDEX_THRESHOLD, 4] {
+        let start = Instant::now();
+        let mut bk = BkTree::with_index_threshold(ManhattanDistance, threshold);
+        bk.insert_all(words.iter().copied());
+        let inserted = start.elapsed();
+
+        let start = Instant::now();
+        let found: usize = (0..1_000).map(|i| bk.find([i * 20], 1).len()).sum();
+        let searched = start.elapsed();
+
+        println!(
+            "threshold {:>20}: insert {:>10.2?}, find {:>10.2?} ({} matches)",
+            threshold, inserted, searched, found
+        );
+    }
+}
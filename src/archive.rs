@@ -0,0 +1,39 @@
+use crate::{ArchivedBkTree, ArchivedNode, Distance};
+use rkyv::Archive;
+use std::borrow::Borrow;
+use std::collections::VecDeque;
+
+impl<T: Archive, D: Archive> ArchivedBkTree<T, D> {
+    /// Find the closest elements to a given value present in the archived BK-tree, without
+    /// deserializing it
+    ///
+    /// The distance function is provided separately and compares the query to the archived
+    /// form of the elements, e.g. a `BkTree<String>` archives its elements as `ArchivedString`
+    /// which can be searched with a `&str`.
+    ///
+    /// Returns pairs of archived element references and distances
+    pub fn find<Q, E>(&self, dist: &E, val: &Q, max_dist: isize) -> Vec<(&T::Archived, isize)>
+    where
+        Q: ?Sized,
+        T::Archived: Borrow<Q>,
+        E: Distance<Q>,
+    {
+        let mut found = Vec::new();
+
+        let mut candidates: VecDeque<&ArchivedNode<T>> = self.root.as_ref().into_iter().collect();
+        while let Some(n) = candidates.pop_front() {
+            let distance = dist.distance(n.word.borrow(), val);
+            if distance <= max_dist {
+                found.push((&n.word, distance));
+            }
+
+            candidates.extend(
+                n.children
+                    .iter()
+                    .filter(|child| (child.0.to_native() as isize - distance).abs() <= max_dist)
+                    .map(|child| &child.1),
+            );
+        }
+        found
+    }
+}
@@ -0,0 +1,72 @@
+//! Breadth-first serialization of BK-trees which can be deserialized incrementally
+//!
+//! The BK-tree is written as the sequence of its elements in breadth-first order. Inserting them
+//! in that order rebuilds the same BK-tree, so [`deserialize_streaming`] inserts each element as
+//! soon as it is read instead of materializing the whole sequence first. With a deserializer
+//! reading from an `io::Read`, such as bincode's `Deserializer::with_reader`, peak memory stays
+//! close to the size of the BK-tree being built.
+use crate::{BkTree, Distance, Node};
+use serde::de::{SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Serialize the elements of a BK-tree in breadth-first order
+///
+/// The distance function and settings of the BK-tree are not written.
+pub fn serialize<T, D, S>(tree: &BkTree<T, D>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize,
+    S: Serializer,
+{
+    let mut queue: VecDeque<&Node<T>> = tree.root.iter().collect();
+    let mut nodes = Vec::new();
+    while let Some(n) = queue.pop_front() {
+        nodes.push(&n.word);
+        queue.extend(n.children.iter().map(|(_, child)| child));
+    }
+    serializer.collect_seq(nodes)
+}
+
+/// Build a BK-tree under `dist` by inserting the elements written by [`serialize`] as they are
+/// deserialized
+///
+/// The returned BK-tree uses the default settings of [`BkTree::new`], and only matches the
+/// serialized one if it was built with the same distance function and settings. Rebuilding
+/// computes about as many distances as inserting the elements.
+pub fn deserialize_streaming<'de, T, D, De>(
+    dist: D,
+    deserializer: De,
+) -> Result<BkTree<T, D>, De::Error>
+where
+    T: Deserialize<'de>,
+    D: Distance<T>,
+    De: Deserializer<'de>,
+{
+    struct InsertingVisitor<T, D> {
+        tree: BkTree<T, D>,
+    }
+
+    impl<'de, T, D> Visitor<'de> for InsertingVisitor<T, D>
+    where
+        T: Deserialize<'de>,
+        D: Distance<T>,
+    {
+        type Value = BkTree<T, D>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a sequence of BK-tree elements in breadth-first order")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<Self::Value, A::Error> {
+            while let Some(word) = seq.next_element()? {
+                self.tree.insert(word);
+            }
+            Ok(self.tree)
+        }
+    }
+
+    deserializer.deserialize_seq(InsertingVisitor {
+        tree: BkTree::new(dist),
+    })
+}
@@ -0,0 +1,126 @@
+use crate::{BkTree, Distance, LevenshteinDistance};
+use std::cell::Cell;
+
+/// Distance over elements stamped with their last access, which only looks at the elements
+#[derive(Debug, Clone)]
+struct EntryDistance<D>(D);
+
+impl<T, D: Distance<T>> Distance<(T, Cell<u64>)> for EntryDistance<D> {
+    fn distance(&self, a: &(T, Cell<u64>), b: &(T, Cell<u64>)) -> isize {
+        self.0.distance(&a.0, &b.0)
+    }
+}
+
+/// A BK-tree holding at most `capacity` elements, evicting the least recently used one to make
+/// room for new elements
+///
+/// Elements are used when they are inserted and whenever a search finds them. Evicting an
+/// element takes a traversal of the whole BK-tree to find it, plus the reinsertion of its
+/// descendants.
+///
+/// ```rust
+/// use bktree::*;
+///
+/// let mut bk = BoundedBkTree::new(LevenshteinDistance, 2);
+/// bk.insert("book");
+/// bk.insert("cake");
+/// bk.find("bo", 2);
+/// bk.insert("cape");
+///
+/// assert_eq!(bk.len(), 2);
+/// assert!(bk.find("cake", 0).is_empty());
+/// ```
+#[derive(Clone)]
+pub struct BoundedBkTree<T, D = LevenshteinDistance> {
+    tree: BkTree<(T, Cell<u64>), EntryDistance<D>>,
+    capacity: usize,
+    len: usize,
+    clock: Cell<u64>,
+}
+
+impl<T, D> BoundedBkTree<T, D>
+where
+    D: Distance<T>,
+{
+    /// Create a new bounded BK-tree with a given distance function and maximum number of
+    /// elements
+    pub fn new(dist: D, capacity: usize) -> Self {
+        Self {
+            tree: BkTree::new(EntryDistance(dist)),
+            capacity,
+            len: 0,
+            clock: Cell::new(0),
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        let now = self.clock.get();
+        self.clock.set(now + 1);
+        now
+    }
+
+    /// Insert a new element in the bounded BK-tree, evicting the least recently used element if
+    /// the bounded BK-tree is full
+    ///
+    /// Returns `false` if an element at distance 0 was already present, in which case the
+    /// bounded BK-tree is left unchanged
+    pub fn insert(&mut self, val: T) -> bool
+    where
+        T: Clone,
+    {
+        let entry = (val, Cell::new(self.tick()));
+        if self.capacity == 0 || self.tree.contains_within(&entry, 0) {
+            return false;
+        }
+        if self.len == self.capacity {
+            let lru = self
+                .tree
+                .iter()
+                .min_by_key(|(_, used)| used.get())
+                .map(|(word, _)| (word.clone(), Cell::new(0)));
+            if let Some(lru) = lru {
+                self.len -= self.tree.remove_within(&lru, 0);
+            }
+        }
+        self.tree.insert(entry);
+        self.len += 1;
+        true
+    }
+
+    /// Find the closest elements to a given value present in the bounded BK-tree, marking them
+    /// as used
+    ///
+    /// Returns pairs of element references and distances
+    pub fn find(&self, val: T, max_dist: isize) -> Vec<(&T, isize)> {
+        let EntryDistance(dist) = &self.tree.dist;
+        let now = self.tick();
+        self.tree
+            .find_by(|(word, _)| dist.distance(word, &val), max_dist)
+            .into_iter()
+            .map(|((word, used), distance)| {
+                used.set(now);
+                (word, distance)
+            })
+            .collect()
+    }
+
+    /// Get the maximum number of elements of the bounded BK-tree
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Count the elements of the bounded BK-tree
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether the bounded BK-tree holds no element
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Create an iterator over references of the elements, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.tree.iter().map(|(word, _)| word)
+    }
+}
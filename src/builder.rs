@@ -0,0 +1,127 @@
+use crate::dedup::CanonicalDedup;
+use crate::{BkTree, Distance, DEFAULT_INDEX_THRESHOLD};
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// A builder gathering the construction options of a [`BkTree`]
+///
+/// ```rust
+/// use bktree::*;
+///
+/// let bk = BkTreeBuilder::new(LevenshteinDistance)
+///     .multiset(true)
+///     .balanced(true)
+///     .build_from(vec!["book", "boo", "book", "cake"]);
+/// assert_eq!(bk.len(), 4);
+/// assert_eq!(bk.find("book", 0).len(), 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BkTreeBuilder<T, D> {
+    dist: D,
+    balanced: bool,
+    capacity: usize,
+    multiset: bool,
+    index_threshold: usize,
+    canonical_dedup: Option<CanonicalDedup<T>>,
+    _elements: PhantomData<fn() -> T>,
+}
+
+impl<T, D> BkTreeBuilder<T, D>
+where
+    D: Distance<T>,
+{
+    /// Create a new builder with a given distance function and the default options of
+    /// [`BkTree::new`]
+    pub fn new(dist: D) -> Self {
+        Self {
+            dist,
+            balanced: false,
+            capacity: 0,
+            multiset: false,
+            index_threshold: DEFAULT_INDEX_THRESHOLD,
+            canonical_dedup: None,
+            _elements: PhantomData,
+        }
+    }
+
+    /// Use another distance function
+    pub fn distance<D2: Distance<T>>(self, dist: D2) -> BkTreeBuilder<T, D2> {
+        BkTreeBuilder {
+            dist,
+            balanced: self.balanced,
+            capacity: self.capacity,
+            multiset: self.multiset,
+            index_threshold: self.index_threshold,
+            canonical_dedup: self.canonical_dedup,
+            _elements: PhantomData,
+        }
+    }
+
+    /// Rebuild the BK-tree made by [`build_from`](Self::build_from) as by
+    /// [`BkTree::rebuild`] once all elements are inserted
+    ///
+    /// This has no effect on [`build`](Self::build), whose BK-tree is empty: call
+    /// [`BkTree::rebuild`] once it is filled instead.
+    pub fn balanced(mut self, balanced: bool) -> Self {
+        self.balanced = balanced;
+        self
+    }
+
+    /// Expect about `capacity` elements, reserving room for their canonical forms if
+    /// [`dedup`](Self::dedup) is set
+    ///
+    /// Nodes are allocated one at a time as elements are inserted, each holding its children,
+    /// so there is no other storage to reserve ahead.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Keep elements at distance 0 of an existing one, see [`BkTree::with_multiset`]
+    pub fn multiset(mut self, multiset: bool) -> Self {
+        self.multiset = multiset;
+        self
+    }
+
+    /// Index the children of nodes once they have more than `threshold` children, see
+    /// [`BkTree::with_index_threshold`]
+    pub fn index_threshold(mut self, threshold: usize) -> Self {
+        self.index_threshold = threshold;
+        self
+    }
+
+    /// Treat elements with the same image by `canonicalize` as duplicates at insertion, see
+    /// [`BkTree::with_canonical_dedup`]
+    pub fn dedup<F>(mut self, canonicalize: F) -> Self
+    where
+        T: Eq + Hash,
+        F: Fn(&T) -> T + Send + Sync + 'static,
+    {
+        self.canonical_dedup = Some(CanonicalDedup::new(Arc::new(canonicalize)));
+        self
+    }
+
+    /// Create an empty BK-tree with the configured options
+    pub fn build(self) -> BkTree<T, D> {
+        let mut tree = BkTree::from_parts(None, self.dist);
+        tree.multiset = self.multiset;
+        tree.index_threshold = self.index_threshold;
+        let capacity = self.capacity;
+        tree.canonical_dedup = self
+            .canonical_dedup
+            .map(|dedup| dedup.with_capacity(capacity));
+        tree
+    }
+
+    /// Create a BK-tree with the configured options holding the elements of a given iterator
+    pub fn build_from<I: IntoIterator<Item = T>>(self, iter: I) -> BkTree<T, D> {
+        let balanced = self.balanced;
+        let mut tree = self.build();
+        tree.insert_all(iter);
+        if balanced {
+            tree.rebuild();
+        }
+        tree
+    }
+}
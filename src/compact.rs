@@ -0,0 +1,92 @@
+//! Compact serialization of BK-trees storing each distinct element once
+//!
+//! Use it with `#[serde(with = "bktree::compact")]` on a `BkTree` field. Elements are written
+//! once in a dictionary and nodes refer to them by index, which shrinks the output when many
+//! nodes hold equal elements, e.g. the repeated elements of a
+//! [multiset](crate::BkTree::with_multiset) BK-tree.
+use crate::{BkTree, Node};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Nodes in pre-order as `(dictionary index, edge from parent, number of children)`, the root
+/// having an edge of 0
+#[derive(Serialize, Deserialize)]
+struct CompactRepr<T, D> {
+    values: Vec<T>,
+    nodes: Vec<(usize, isize, usize)>,
+    dist: D,
+    #[serde(default)]
+    multiset: bool,
+}
+
+/// Serialize a BK-tree in the compact format
+pub fn serialize<T, D, S>(tree: &BkTree<T, D>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize + Eq + Hash,
+    D: Serialize,
+    S: Serializer,
+{
+    let mut values: Vec<&T> = Vec::new();
+    let mut indices: HashMap<&T, usize> = HashMap::new();
+    let mut nodes = Vec::new();
+
+    let mut stack: Vec<(isize, &Node<T>)> = tree.root.iter().map(|root| (0, root)).collect();
+    while let Some((arc, n)) = stack.pop() {
+        let index = *indices.entry(&n.word).or_insert_with(|| {
+            values.push(&n.word);
+            values.len() - 1
+        });
+        nodes.push((index, arc, n.children.len()));
+        stack.extend(n.children.iter().rev().map(|(arc, child)| (*arc, child)));
+    }
+
+    CompactRepr {
+        values,
+        nodes,
+        dist: &tree.dist,
+        multiset: tree.multiset,
+    }
+    .serialize(serializer)
+}
+
+/// Deserialize a BK-tree from the compact format
+pub fn deserialize<'de, T, D, De>(deserializer: De) -> Result<BkTree<T, D>, De::Error>
+where
+    T: Deserialize<'de> + Clone,
+    D: Deserialize<'de>,
+    De: Deserializer<'de>,
+{
+    fn build<T: Clone, E: Error>(
+        values: &[T],
+        nodes: &mut std::slice::Iter<(usize, isize, usize)>,
+    ) -> Result<(isize, Node<T>), E> {
+        let (index, arc, child_count) = nodes
+            .next()
+            .ok_or_else(|| E::custom("missing BK-tree node"))?;
+        let word = values
+            .get(*index)
+            .ok_or_else(|| E::custom("BK-tree node refers to an unknown element"))?
+            .clone();
+        let children = (0..*child_count)
+            .map(|_| build(values, nodes))
+            .collect::<Result<_, E>>()?;
+        Ok((*arc, Node::with_children(word, children)))
+    }
+
+    let repr = CompactRepr::<T, D>::deserialize(deserializer)?;
+    let mut nodes = repr.nodes.iter();
+    let root = if repr.nodes.is_empty() {
+        None
+    } else {
+        Some(build(&repr.values, &mut nodes)?.1)
+    };
+    if nodes.next().is_some() {
+        return Err(De::Error::custom("unreachable BK-tree nodes"));
+    }
+
+    let mut tree = BkTree::from_parts(root, repr.dist);
+    tree.multiset = repr.multiset;
+    Ok(tree)
+}
@@ -0,0 +1,104 @@
+use crate::Canonicalize;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::Hash;
+
+/// Canonical forms of the elements of a BK-tree, looked up to detect duplicates at insertion
+pub(crate) struct CanonicalDedup<T> {
+    canonicalize: Canonicalize<T>,
+    keys: HashSet<T>,
+    // Set operations, instantiated where `T: Eq + Hash` is known so that insertions do not need
+    // those bounds
+    insert_key: fn(&mut HashSet<T>, T) -> bool,
+    contains_key: fn(&HashSet<T>, &T) -> bool,
+    eq: fn(&T, &T) -> bool,
+}
+
+impl<T> CanonicalDedup<T> {
+    pub(crate) fn new(canonicalize: Canonicalize<T>) -> Self
+    where
+        T: Eq + Hash,
+    {
+        Self {
+            canonicalize,
+            keys: HashSet::new(),
+            insert_key: HashSet::insert,
+            contains_key: HashSet::contains,
+            eq: T::eq,
+        }
+    }
+
+    /// Create the same canonicalization, without any recorded canonical form
+    pub(crate) fn emptied(&self) -> Self {
+        Self {
+            canonicalize: self.canonicalize.clone(),
+            keys: HashSet::new(),
+            insert_key: self.insert_key,
+            contains_key: self.contains_key,
+            eq: self.eq,
+        }
+    }
+
+    /// Get the canonical form of `val`, or `None` if an element with the same canonical form
+    /// was recorded
+    pub(crate) fn check(&self, val: &T) -> Option<T> {
+        let key = (self.canonicalize)(val);
+        (!(self.contains_key)(&self.keys, &key)).then_some(key)
+    }
+
+    /// Check whether two elements have the same canonical form
+    pub(crate) fn same_form(&self, a: &T, b: &T) -> bool {
+        (self.eq)(&(self.canonicalize)(a), &(self.canonicalize)(b))
+    }
+
+    /// Record the canonical form of an inserted element, as returned by [`check`](Self::check)
+    pub(crate) fn record(&mut self, key: T) {
+        (self.insert_key)(&mut self.keys, key);
+    }
+
+    /// Reserve room for `capacity` canonical forms, before any is recorded
+    pub(crate) fn with_capacity(mut self, capacity: usize) -> Self {
+        debug_assert!(self.keys.is_empty());
+        self.keys = HashSet::with_capacity(capacity);
+        self
+    }
+
+    /// Forget the recorded canonical forms and record those of `elements` instead
+    pub(crate) fn reset<'a, I: Iterator<Item = &'a T>>(&mut self, elements: I)
+    where
+        T: 'a,
+    {
+        self.keys.clear();
+        for val in elements {
+            let key = (self.canonicalize)(val);
+            self.record(key);
+        }
+    }
+}
+
+impl<T: Clone> Clone for CanonicalDedup<T> {
+    fn clone(&self) -> Self {
+        Self {
+            keys: self.keys.clone(),
+            ..self.emptied()
+        }
+    }
+}
+
+impl<T> fmt::Debug for CanonicalDedup<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CanonicalDedup")
+            .field("keys", &self.keys.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Outcome of checking an element against the canonical forms of a BK-tree's elements
+pub(crate) enum Canonical<T> {
+    /// The BK-tree does not deduplicate by canonical form
+    Untracked,
+    /// An element with the same canonical form is stored
+    Duplicate,
+    /// The canonical form of the element, to record once it is inserted
+    New(T),
+}
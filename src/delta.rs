@@ -0,0 +1,142 @@
+#[cfg(feature = "serde-support")]
+use crate::{BkTree, Node};
+#[cfg(feature = "serde-support")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "serde-support")]
+use std::collections::BTreeSet;
+
+/// Subtrees of the root changed since the BK-tree was last marked clean, identified by the
+/// distance of their edge
+#[cfg(feature = "serde-support")]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DirtySubtrees {
+    all: bool,
+    edges: BTreeSet<isize>,
+}
+
+/// Without serialization there is nothing to track changes for
+#[cfg(not(feature = "serde-support"))]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DirtySubtrees;
+
+impl DirtySubtrees {
+    #[cfg(feature = "serde-support")]
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    #[cfg(feature = "serde-support")]
+    pub(crate) fn mark_all(&mut self) {
+        self.all = true;
+        self.edges.clear();
+    }
+
+    #[cfg(feature = "serde-support")]
+    pub(crate) fn mark(&mut self, edge: isize) {
+        if !self.all {
+            self.edges.insert(edge);
+        }
+    }
+
+    #[cfg(not(feature = "serde-support"))]
+    pub(crate) fn new() -> Self {
+        DirtySubtrees
+    }
+
+    #[cfg(not(feature = "serde-support"))]
+    pub(crate) fn mark_all(&mut self) {}
+
+    #[cfg(not(feature = "serde-support"))]
+    pub(crate) fn mark(&mut self, _edge: isize) {}
+}
+
+#[cfg(feature = "serde-support")]
+#[derive(Serialize)]
+enum DeltaRef<'a, T> {
+    Full(&'a Option<Node<T>>),
+    Subtrees(Vec<(isize, Option<&'a Node<T>>)>),
+}
+
+#[cfg(feature = "serde-support")]
+#[derive(Deserialize)]
+enum Delta<T> {
+    Full(Option<Node<T>>),
+    Subtrees(Vec<(isize, Option<Node<T>>)>),
+}
+
+#[cfg(feature = "serde-support")]
+impl<T, D> BkTree<T, D> {
+    /// Serialize the subtrees changed since the BK-tree was last marked clean
+    ///
+    /// Applying the delta with [`apply_delta`](Self::apply_delta) to a snapshot of the BK-tree
+    /// taken when it was last marked clean brings the snapshot up to date. Changes are tracked
+    /// per child of the root, and any change of the root itself makes the delta hold the whole
+    /// BK-tree.
+    pub fn serialize_delta<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize,
+    {
+        let delta = if self.dirty.all {
+            DeltaRef::Full(&self.root)
+        } else {
+            let children = self
+                .root
+                .as_ref()
+                .map_or(&[][..], |root| &root.children[..]);
+            DeltaRef::Subtrees(
+                self.dirty
+                    .edges
+                    .iter()
+                    .map(|edge| {
+                        let child = children.iter().find(|(arc, _)| arc == edge);
+                        (*edge, child.map(|(_, node)| node))
+                    })
+                    .collect(),
+            )
+        };
+        delta.serialize(serializer)
+    }
+
+    /// Apply a delta produced by [`serialize_delta`](Self::serialize_delta) to this snapshot
+    pub fn apply_delta<'de, De: Deserializer<'de>>(
+        &mut self,
+        deserializer: De,
+    ) -> Result<(), De::Error>
+    where
+        T: Deserialize<'de>,
+    {
+        match Delta::deserialize(deserializer)? {
+            Delta::Full(root) => self.root = root,
+            Delta::Subtrees(subtrees) => {
+                let root = match self.root {
+                    Some(ref mut root) => root,
+                    None if subtrees.is_empty() => return Ok(()),
+                    None => {
+                        return Err(serde::de::Error::custom(
+                            "cannot apply a BK-tree delta to an empty BK-tree",
+                        ))
+                    }
+                };
+                for (edge, subtree) in subtrees {
+                    let pos = root.children.iter().position(|(arc, _)| *arc == edge);
+                    match (pos, subtree) {
+                        (Some(pos), Some(subtree)) => root.children[pos].1 = subtree,
+                        (Some(pos), None) => {
+                            root.children.remove(pos);
+                        }
+                        (None, Some(subtree)) => root.children.push((edge, subtree)),
+                        (None, None) => {}
+                    }
+                }
+                root.reindex(self.index_threshold);
+            }
+        }
+        self.reset_canonical_keys();
+        Ok(())
+    }
+
+    /// Forget about the changes made so far, typically after persisting the BK-tree or a delta
+    pub fn mark_clean(&mut self) {
+        self.dirty = DirtySubtrees::new();
+    }
+}
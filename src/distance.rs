@@ -1,21 +1,483 @@
+use crate::BkError;
+use std::collections::{HashMap, HashSet};
+
 pub trait Distance<T: ?Sized> {
     fn distance(&self, a: &T, b: &T) -> isize;
+
+    /// Largest distance this function can return between any two values, if known
+    ///
+    /// Searches within a radius at least this large match every element, so they skip pruning.
+    fn bounded_max(&self) -> Option<isize> {
+        None
+    }
+}
+
+/// A distance function able to tell the largest distance possible between two values, which
+/// allows normalizing distances into similarity ratios
+pub trait NormalizedDistance<T: ?Sized>: Distance<T> {
+    fn max_distance(&self, a: &T, b: &T) -> isize;
 }
 
+/// A distance function able to preprocess a query once before comparing it to many candidates
+///
+/// Metrics without any useful preprocessing can be wrapped in [`Unprepared`], which uses the
+/// query itself as `Prepared`. A blanket implementation for every [`Distance`] is not possible,
+/// since it would overlap with the implementations of metrics which do preprocess queries.
+pub trait PreparedDistance<T: ?Sized>: Distance<T> {
+    type Prepared;
+
+    fn prepare(&self, query: &T) -> Self::Prepared;
+
+    fn distance_prepared(&self, prepared: &Self::Prepared, candidate: &T) -> isize;
+}
+
+/// Distance function without query preprocessing, forwarding to `D`
+///
+/// This makes any distance function usable with [`BkTree::find_prepared`], preparing a
+/// query by cloning it.
+///
+/// [`BkTree::find_prepared`]: crate::BkTree::find_prepared
 #[cfg_attr(
     feature = "serde-support",
     derive(serde::Serialize, serde::Deserialize)
 )]
-#[derive(Debug)]
+#[cfg_attr(
+    feature = "rkyv-support",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+#[derive(Debug, Clone)]
+pub struct Unprepared<D>(pub D);
+
+impl<T: ?Sized, D: Distance<T>> Distance<T> for Unprepared<D> {
+    fn distance(&self, a: &T, b: &T) -> isize {
+        self.0.distance(a, b)
+    }
+
+    fn bounded_max(&self) -> Option<isize> {
+        self.0.bounded_max()
+    }
+}
+
+impl<T: Clone, D: Distance<T>> PreparedDistance<T> for Unprepared<D> {
+    type Prepared = T;
+
+    fn prepare(&self, query: &T) -> T {
+        query.clone()
+    }
+
+    fn distance_prepared(&self, prepared: &T, candidate: &T) -> isize {
+        self.0.distance(prepared, candidate)
+    }
+}
+
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "rkyv-support",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+#[derive(Debug, Clone)]
 pub struct HammingDistance;
 
 #[cfg_attr(
     feature = "serde-support",
     derive(serde::Serialize, serde::Deserialize)
 )]
-#[derive(Debug)]
+#[cfg_attr(
+    feature = "rkyv-support",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+#[derive(Debug, Clone)]
 pub struct LevenshteinDistance;
 
+/// Suffix-anchored distance between strings, by characters: the number of characters of the
+/// longer string outside the longest common suffix of both
+///
+/// Words ending the same way are close regardless of how they start, e.g. "tion" is at distance
+/// 2 of "nation" and "motion", while any difference near the end of the words costs all the
+/// characters before it: "tion" is at distance 5 of "tiona", which is a single edit away under
+/// [`LevenshteinDistance`]. This is a metric, which is never smaller than the Levenshtein
+/// distance, and is computed in linear time.
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "rkyv-support",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+#[derive(Debug, Clone)]
+pub struct SuffixDistance;
+
+/// Levenshtein distance between byte strings, counting edits of single bytes
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "rkyv-support",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+#[derive(Debug, Clone)]
+pub struct ByteLevenshteinDistance;
+
+/// Levenshtein distance in which substituting a character by one on an adjacent key costs less
+///
+/// To keep distances integral, insertions, deletions and substitutions cost 2 while
+/// substitutions between adjacent keys cost 1, so distances are twice those of
+/// [`LevenshteinDistance`] when no adjacent keys are involved. Adjacency is symmetric and
+/// compared case-insensitively. Since every substitution costs at least half of any other, this
+/// is a metric for any adjacency map, which makes it safe to use with a BK-tree.
+///
+/// ```rust
+/// use bktree::*;
+///
+/// let dist = KeyboardLevenshtein::default();
+/// assert_eq!(dist.distance("cat", "cay"), 1);
+/// assert_eq!(dist.distance("cat", "cam"), 2);
+/// assert_eq!(dist.distance("cat", "cats"), 2);
+/// ```
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[derive(Debug, Clone)]
+pub struct KeyboardLevenshtein {
+    adjacent: HashSet<(char, char)>,
+}
+
+impl KeyboardLevenshtein {
+    /// Create a keyboard-aware Levenshtein distance from pairs of adjacent keys
+    pub fn new<I: IntoIterator<Item = (char, char)>>(pairs: I) -> Self {
+        let mut adjacent = HashSet::new();
+        for (a, b) in pairs {
+            let (a, b) = (
+                a.to_lowercase().next().unwrap_or(a),
+                b.to_lowercase().next().unwrap_or(b),
+            );
+            adjacent.insert((a, b));
+            adjacent.insert((b, a));
+        }
+        Self { adjacent }
+    }
+
+    /// Create a keyboard-aware Levenshtein distance for a staggered layout given by its rows of
+    /// keys from top to bottom
+    ///
+    /// Each key is adjacent to its neighbors on its row and to the two keys below it, the
+    /// lower row being shifted right.
+    pub fn from_rows(rows: &[&str]) -> Self {
+        let rows: Vec<Vec<char>> = rows.iter().map(|row| row.chars().collect()).collect();
+        let mut pairs = Vec::new();
+        for (r, row) in rows.iter().enumerate() {
+            for (i, key) in row.iter().enumerate() {
+                if let Some(next) = row.get(i + 1) {
+                    pairs.push((*key, *next));
+                }
+                if let Some(below) = rows.get(r + 1) {
+                    let left = i.checked_sub(1).and_then(|j| below.get(j));
+                    pairs.extend(left.into_iter().chain(below.get(i)).map(|b| (*key, *b)));
+                }
+            }
+        }
+        Self::new(pairs)
+    }
+
+    fn substitution_cost(&self, a: char, b: char) -> isize {
+        if a == b {
+            0
+        } else if self.adjacent.contains(&(
+            a.to_lowercase().next().unwrap_or(a),
+            b.to_lowercase().next().unwrap_or(b),
+        )) {
+            1
+        } else {
+            2
+        }
+    }
+}
+
+impl Default for KeyboardLevenshtein {
+    /// Adjacency of the letter keys of a QWERTY keyboard
+    fn default() -> Self {
+        Self::from_rows(&["qwertyuiop", "asdfghjkl", "zxcvbnm"])
+    }
+}
+
+impl<T: AsRef<str> + ?Sized> Distance<T> for KeyboardLevenshtein {
+    fn distance(&self, a: &T, b: &T) -> isize {
+        let a: Vec<char> = a.as_ref().chars().collect();
+        let b: Vec<char> = b.as_ref().chars().collect();
+
+        let mut prev: Vec<isize> = (0..=b.len() as isize).map(|j| 2 * j).collect();
+        let mut cur = vec![0; b.len() + 1];
+        for (i, ca) in a.iter().enumerate() {
+            cur[0] = 2 * (i as isize + 1);
+            for (j, cb) in b.iter().enumerate() {
+                cur[j + 1] = (prev[j] + self.substitution_cost(*ca, *cb))
+                    .min(prev[j + 1] + 2)
+                    .min(cur[j] + 2);
+            }
+            std::mem::swap(&mut prev, &mut cur);
+        }
+        prev[b.len()]
+    }
+}
+
+/// Number of insertions and deletions needed to turn a string into another, i.e.
+/// `|a| + |b| - 2 * |LCS(a, b)|`
+///
+/// Distances between strings whose lengths have the same parity are always even.
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "rkyv-support",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+#[derive(Debug, Clone)]
+pub struct LcsDistance;
+
+/// Sum of absolute coordinate differences between fixed-dimension vectors
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "rkyv-support",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+#[derive(Debug, Clone)]
+pub struct ManhattanDistance;
+
+/// Levenshtein distance between the Metaphone codes of two strings, so that words which sound
+/// alike in English are close
+///
+/// This is not a strict metric: distinct words with the same code, such as "Smith" and
+/// "Smyth", are at distance 0, so a BK-tree only keeps the first one inserted unless it is a
+/// [multiset](crate::BkTree::with_multiset). It does satisfy the triangle inequality, but codes
+/// are coarse, so prefer generous radii and filtering the candidates found with another
+/// distance function.
+///
+/// ```rust
+/// use bktree::*;
+///
+/// assert_eq!(MetaphoneDistance::encode("Knight"), "NT");
+/// assert_eq!(MetaphoneDistance.distance("Smith", "Smyth"), 0);
+/// ```
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "rkyv-support",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+#[derive(Debug, Clone)]
+pub struct MetaphoneDistance;
+
+impl MetaphoneDistance {
+    /// Compute the Metaphone code of a string, ignoring case and anything but ASCII letters
+    pub fn encode(word: &str) -> String {
+        let mut w: Vec<u8> = word
+            .bytes()
+            .filter(u8::is_ascii_alphabetic)
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        match w.as_slice() {
+            [b'A', b'E', ..] | [b'G' | b'K' | b'P', b'N', ..] | [b'W', b'R', ..] => {
+                w.remove(0);
+            }
+            [b'W', b'H', ..] => {
+                w.remove(1);
+            }
+            [b'X', ..] => w[0] = b'S',
+            _ => {}
+        }
+
+        let is_vowel = |c: Option<&u8>| matches!(c, Some(b'A' | b'E' | b'I' | b'O' | b'U'));
+        let is_front = |c: Option<&u8>| matches!(c, Some(b'E' | b'I' | b'Y'));
+        let mut code = String::new();
+        for i in 0..w.len() {
+            let c = w[i];
+            let prev = i.checked_sub(1).map(|i| &w[i]);
+            let next = w.get(i + 1);
+            let after_next = w.get(i + 2);
+            if prev == Some(&c) && c != b'C' {
+                continue;
+            }
+            match c {
+                b'A' | b'E' | b'I' | b'O' | b'U' => {
+                    if i == 0 {
+                        code.push(c as char);
+                    }
+                }
+                b'B' => {
+                    if !(prev == Some(&b'M') && next.is_none()) {
+                        code.push('B');
+                    }
+                }
+                b'C' => {
+                    if next == Some(&b'I') && after_next == Some(&b'A') {
+                        code.push('X');
+                    } else if next == Some(&b'H') {
+                        code.push(if prev == Some(&b'S') { 'K' } else { 'X' });
+                    } else if is_front(next) {
+                        if prev != Some(&b'S') {
+                            code.push('S');
+                        }
+                    } else {
+                        code.push('K');
+                    }
+                }
+                b'D' => {
+                    if next == Some(&b'G') && is_front(after_next) {
+                        code.push('J');
+                    } else {
+                        code.push('T');
+                    }
+                }
+                b'G' => {
+                    let silent_gh =
+                        next == Some(&b'H') && after_next.is_some() && !is_vowel(after_next);
+                    let silent_gn =
+                        next == Some(&b'N') && (after_next.is_none() || w[i + 2..] == *b"ED");
+                    if silent_gh || silent_gn || prev == Some(&b'D') && is_front(next) {
+                        continue;
+                    }
+                    code.push(if is_front(next) { 'J' } else { 'K' });
+                }
+                b'H' => {
+                    let after_modifier = matches!(prev, Some(b'C' | b'S' | b'P' | b'T' | b'G'));
+                    let silent_after_vowel = is_vowel(prev) && !is_vowel(next);
+                    if !(after_modifier || silent_after_vowel) {
+                        code.push('H');
+                    }
+                }
+                b'K' => {
+                    if prev != Some(&b'C') {
+                        code.push('K');
+                    }
+                }
+                b'P' => code.push(if next == Some(&b'H') { 'F' } else { 'P' }),
+                b'Q' => code.push('K'),
+                b'S' => {
+                    if next == Some(&b'H')
+                        || next == Some(&b'I') && matches!(after_next, Some(b'O' | b'A'))
+                    {
+                        code.push('X');
+                    } else {
+                        code.push('S');
+                    }
+                }
+                b'T' => {
+                    if next == Some(&b'I') && matches!(after_next, Some(b'O' | b'A')) {
+                        code.push('X');
+                    } else if next == Some(&b'H') {
+                        code.push('0');
+                    } else if !(next == Some(&b'C') && after_next == Some(&b'H')) {
+                        code.push('T');
+                    }
+                }
+                b'V' => code.push('F'),
+                b'W' | b'Y' => {
+                    if is_vowel(next) {
+                        code.push(c as char);
+                    }
+                }
+                b'X' => code.push_str("KS"),
+                b'Z' => code.push('S'),
+                _ => code.push(c as char),
+            }
+        }
+        code
+    }
+}
+
+impl<T: AsRef<str> + ?Sized> Distance<T> for MetaphoneDistance {
+    fn distance(&self, a: &T, b: &T) -> isize {
+        let a = Self::encode(a.as_ref());
+        let b = Self::encode(b.as_ref());
+        LevenshteinDistance.distance(&a, &b)
+    }
+}
+
+/// Sørensen–Dice distance over character bigrams, scaled to an integer:
+/// `scale * (1 - 2|A∩B| / (|A| + |B|))`, rounded down
+///
+/// Dice distance does not satisfy the triangle inequality in general, so a BK-tree using it may
+/// miss some matches. Prefer it for ranking short strings rather than for exhaustive searches.
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "rkyv-support",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+#[derive(Debug, Clone)]
+pub struct DiceDistance {
+    pub scale: isize,
+}
+
+impl<T: AsRef<str> + ?Sized> Distance<T> for DiceDistance {
+    fn distance(&self, a: &T, b: &T) -> isize {
+        let a = a.as_ref();
+        let b = b.as_ref();
+
+        if a == b {
+            return 0;
+        }
+
+        let bigrams = |s: &str| {
+            let chars: Vec<char> = s.chars().collect();
+            let mut counts: HashMap<(char, char), isize> = HashMap::new();
+            for w in chars.windows(2) {
+                *counts.entry((w[0], w[1])).or_insert(0) += 1;
+            }
+            counts
+        };
+        let a_bigrams = bigrams(a);
+        let b_bigrams = bigrams(b);
+
+        let total: isize = a_bigrams.values().chain(b_bigrams.values()).sum();
+        if total == 0 {
+            return self.scale;
+        }
+        let common: isize = a_bigrams
+            .iter()
+            .map(|(bigram, count)| (*count).min(*b_bigrams.get(bigram).unwrap_or(&0)))
+            .sum();
+
+        self.scale * (total - 2 * common) / total
+    }
+}
+
+impl<T: AsRef<str> + ?Sized> Distance<T> for LcsDistance {
+    fn distance(&self, a: &T, b: &T) -> isize {
+        let a: Vec<char> = a.as_ref().chars().collect();
+        let b: Vec<char> = b.as_ref().chars().collect();
+
+        let mut prev = vec![0; b.len() + 1];
+        let mut cur = vec![0; b.len() + 1];
+        for ca in &a {
+            for (j, cb) in b.iter().enumerate() {
+                cur[j + 1] = if ca == cb {
+                    prev[j] + 1
+                } else {
+                    cur[j].max(prev[j + 1])
+                };
+            }
+            std::mem::swap(&mut prev, &mut cur);
+        }
+
+        (a.len() + b.len() - 2 * prev[b.len()]) as isize
+    }
+}
+
 impl<T: AsRef<str> + ?Sized> Distance<T> for LevenshteinDistance {
     fn distance(&self, a: &T, b: &T) -> isize {
         let a = a.as_ref();
@@ -25,51 +487,416 @@ impl<T: AsRef<str> + ?Sized> Distance<T> for LevenshteinDistance {
             return 0;
         }
 
-        let a_len = a.chars().count();
-        let b_len = b.chars().count();
+        levenshtein(a.chars(), a.chars().count(), b.chars(), b.chars().count())
+    }
+}
+
+/// A step of an alignment between two strings, see [`LevenshteinDistance::alignment`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditOp {
+    /// Keep a character common to both strings
+    Keep(char),
+    /// Replace a character of the first string by a character of the second one
+    Substitute(char, char),
+    /// Insert a character of the second string
+    Insert(char),
+    /// Delete a character of the first string
+    Delete(char),
+}
+
+impl LevenshteinDistance {
+    /// Compute a shortest sequence of edit operations turning `a` into `b`
+    ///
+    /// Operations are listed in string order, so replaying them while reading `a` from left to
+    /// right produces `b`. The number of operations other than [`EditOp::Keep`] is the
+    /// Levenshtein distance between `a` and `b`. This takes a time and memory proportional to
+    /// the product of the lengths of the strings.
+    ///
+    /// ```rust
+    /// use bktree::*;
+    ///
+    /// assert_eq!(
+    ///     LevenshteinDistance.alignment("cat", "cart"),
+    ///     [
+    ///         EditOp::Keep('c'),
+    ///         EditOp::Keep('a'),
+    ///         EditOp::Insert('r'),
+    ///         EditOp::Keep('t'),
+    ///     ]
+    /// );
+    /// ```
+    pub fn alignment(&self, a: &str, b: &str) -> Vec<EditOp> {
+        let a: Vec<char> = a.chars().collect();
+        let b: Vec<char> = b.chars().collect();
 
-        if a_len == 0 {
-            return b_len as isize;
+        // costs[i][j] is the distance between the first i characters of a and j characters of b
+        let mut costs = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for (i, row) in costs.iter_mut().enumerate() {
+            row[0] = i;
+        }
+        for (j, cost) in costs[0].iter_mut().enumerate() {
+            *cost = j;
+        }
+        for i in 1..=a.len() {
+            for j in 1..=b.len() {
+                let substitution = costs[i - 1][j - 1] + (a[i - 1] != b[j - 1]) as usize;
+                costs[i][j] = substitution
+                    .min(costs[i - 1][j] + 1)
+                    .min(costs[i][j - 1] + 1);
+            }
         }
 
-        if b_len == 0 {
-            return a_len as isize;
+        let mut ops = Vec::with_capacity(a.len().max(b.len()));
+        let (mut i, mut j) = (a.len(), b.len());
+        while i > 0 || j > 0 {
+            if i > 0
+                && j > 0
+                && costs[i][j] == costs[i - 1][j - 1] + (a[i - 1] != b[j - 1]) as usize
+            {
+                ops.push(if a[i - 1] == b[j - 1] {
+                    EditOp::Keep(a[i - 1])
+                } else {
+                    EditOp::Substitute(a[i - 1], b[j - 1])
+                });
+                i -= 1;
+                j -= 1;
+            } else if i > 0 && costs[i][j] == costs[i - 1][j] + 1 {
+                ops.push(EditOp::Delete(a[i - 1]));
+                i -= 1;
+            } else {
+                ops.push(EditOp::Insert(b[j - 1]));
+                j -= 1;
+            }
         }
+        ops.reverse();
+        ops
+    }
+}
 
-        let mut res = 0;
-        let mut cache: Vec<usize> = (1..).take(a_len).collect();
-        let mut a_dist;
-        let mut b_dist;
+impl<T: AsRef<str> + ?Sized> PreparedDistance<T> for LevenshteinDistance {
+    type Prepared = Vec<char>;
 
-        for (ib, cb) in b.chars().enumerate() {
-            res = ib;
-            a_dist = ib;
-            for (ia, ca) in a.chars().enumerate() {
-                b_dist = if ca == cb { a_dist } else { a_dist + 1 };
-                a_dist = cache[ia];
+    fn prepare(&self, query: &T) -> Self::Prepared {
+        query.as_ref().chars().collect()
+    }
 
-                res = if a_dist > res {
-                    if b_dist > res {
-                        res + 1
-                    } else {
-                        b_dist
-                    }
-                } else if b_dist > a_dist {
-                    a_dist + 1
+    fn distance_prepared(&self, prepared: &Self::Prepared, candidate: &T) -> isize {
+        let candidate = candidate.as_ref();
+        levenshtein(
+            prepared.iter().copied(),
+            prepared.len(),
+            candidate.chars(),
+            candidate.chars().count(),
+        )
+    }
+}
+
+impl<T: AsRef<str> + ?Sized> Distance<T> for SuffixDistance {
+    fn distance(&self, a: &T, b: &T) -> isize {
+        let a = a.as_ref();
+        let b = b.as_ref();
+
+        let suffix = a
+            .chars()
+            .rev()
+            .zip(b.chars().rev())
+            .take_while(|(x, y)| x == y)
+            .count();
+        (a.chars().count().max(b.chars().count()) - suffix) as isize
+    }
+}
+
+impl<T: AsRef<[u8]> + ?Sized> Distance<T> for ByteLevenshteinDistance {
+    fn distance(&self, a: &T, b: &T) -> isize {
+        let a = a.as_ref();
+        let b = b.as_ref();
+
+        if a == b {
+            return 0;
+        }
+
+        levenshtein(a.iter(), a.len(), b.iter(), b.len())
+    }
+}
+
+fn levenshtein<E, A, B>(a: A, a_len: usize, b: B, b_len: usize) -> isize
+where
+    E: PartialEq,
+    A: Iterator<Item = E> + Clone,
+    B: Iterator<Item = E>,
+{
+    if a_len == 0 {
+        return b_len as isize;
+    }
+
+    if b_len == 0 {
+        return a_len as isize;
+    }
+
+    let mut res = 0;
+    let mut cache: Vec<usize> = (1..).take(a_len).collect();
+    let mut a_dist;
+    let mut b_dist;
+
+    for (ib, cb) in b.enumerate() {
+        res = ib;
+        a_dist = ib;
+        for (ia, ca) in a.clone().enumerate() {
+            b_dist = if ca == cb { a_dist } else { a_dist + 1 };
+            a_dist = cache[ia];
+
+            res = if a_dist > res {
+                if b_dist > res {
+                    res + 1
                 } else {
                     b_dist
-                };
+                }
+            } else if b_dist > a_dist {
+                a_dist + 1
+            } else {
+                b_dist
+            };
 
-                cache[ia] = res;
-            }
+            cache[ia] = res;
         }
-
-        res as isize
     }
+
+    res as isize
 }
 
-impl<T: num::PrimInt + ?Sized> Distance<T> for HammingDistance {
+impl<T: num::PrimInt> Distance<T> for HammingDistance {
     fn distance(&self, a: &T, b: &T) -> isize {
         (*a ^ *b).count_ones() as isize
     }
+
+    fn bounded_max(&self) -> Option<isize> {
+        Some(T::zero().count_zeros() as isize)
+    }
+}
+
+impl<T: num::PrimInt> PreparedDistance<T> for HammingDistance {
+    type Prepared = T;
+
+    fn prepare(&self, query: &T) -> Self::Prepared {
+        *query
+    }
+
+    fn distance_prepared(&self, prepared: &Self::Prepared, candidate: &T) -> isize {
+        self.distance(prepared, candidate)
+    }
+}
+
+impl ManhattanDistance {
+    /// Compute the distance between vectors whose dimension is only known at runtime
+    ///
+    /// Returns [`BkError::DimensionMismatch`] if the vectors have different dimensions, with
+    /// the dimension of `a` as the expected one.
+    pub fn checked_distance(&self, a: &[i64], b: &[i64]) -> Result<isize, BkError> {
+        if a.len() != b.len() {
+            return Err(BkError::DimensionMismatch {
+                expected: a.len(),
+                found: b.len(),
+            });
+        }
+        Ok(a.iter()
+            .zip(b.iter())
+            .map(|(x, y)| (x - y).abs())
+            .sum::<i64>() as isize)
+    }
+}
+
+impl<const N: usize> Distance<[i64; N]> for ManhattanDistance {
+    fn distance(&self, a: &[i64; N], b: &[i64; N]) -> isize {
+        a.iter()
+            .zip(b.iter())
+            .map(|(x, y)| (x - y).abs())
+            .sum::<i64>() as isize
+    }
+}
+
+impl<T: AsRef<str> + ?Sized> NormalizedDistance<T> for LevenshteinDistance {
+    fn max_distance(&self, a: &T, b: &T) -> isize {
+        a.as_ref().chars().count().max(b.as_ref().chars().count()) as isize
+    }
+}
+
+impl<T: AsRef<str> + ?Sized> NormalizedDistance<T> for LcsDistance {
+    fn max_distance(&self, a: &T, b: &T) -> isize {
+        (a.as_ref().chars().count() + b.as_ref().chars().count()) as isize
+    }
+}
+
+impl<T: AsRef<str> + ?Sized> NormalizedDistance<T> for DiceDistance {
+    fn max_distance(&self, _a: &T, _b: &T) -> isize {
+        self.scale
+    }
+}
+
+impl<T: num::PrimInt> NormalizedDistance<T> for HammingDistance {
+    fn max_distance(&self, _a: &T, _b: &T) -> isize {
+        T::zero().count_zeros() as isize
+    }
+}
+
+/// A value made of two parts compared by separate distance functions, see [`SumDistance`] and
+/// [`MaxDistance`]
+pub trait CompositeKey {
+    type First: ?Sized;
+    type Second: ?Sized;
+
+    fn first(&self) -> &Self::First;
+
+    fn second(&self) -> &Self::Second;
+}
+
+impl<X, Y> CompositeKey for (X, Y) {
+    type First = X;
+    type Second = Y;
+
+    fn first(&self) -> &X {
+        &self.0
+    }
+
+    fn second(&self) -> &Y {
+        &self.1
+    }
+}
+
+/// Sum of two distance functions applied to the parts of a [`CompositeKey`]
+///
+/// The sum of two metrics is a metric, so it is safe to use with a BK-tree.
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "rkyv-support",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+#[derive(Debug, Clone)]
+pub struct SumDistance<A, B>(pub A, pub B);
+
+impl<T, A, B> Distance<T> for SumDistance<A, B>
+where
+    T: CompositeKey + ?Sized,
+    A: Distance<T::First>,
+    B: Distance<T::Second>,
+{
+    fn distance(&self, a: &T, b: &T) -> isize {
+        self.0.distance(a.first(), b.first()) + self.1.distance(a.second(), b.second())
+    }
+}
+
+/// Maximum of two distance functions applied to the parts of a [`CompositeKey`]
+///
+/// The maximum of two metrics is a metric, so it is safe to use with a BK-tree.
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "rkyv-support",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+#[derive(Debug, Clone)]
+pub struct MaxDistance<A, B>(pub A, pub B);
+
+impl<T, A, B> Distance<T> for MaxDistance<A, B>
+where
+    T: CompositeKey + ?Sized,
+    A: Distance<T::First>,
+    B: Distance<T::Second>,
+{
+    fn distance(&self, a: &T, b: &T) -> isize {
+        self.0
+            .distance(a.first(), b.first())
+            .max(self.1.distance(a.second(), b.second()))
+    }
+}
+
+/// Distance function over optional values, applying `inner` between present values
+///
+/// Two missing values are at distance 0 and a present value is at distance `penalty` of a
+/// missing one. This is a metric when `inner` is one, `penalty` is positive and `2 * penalty`
+/// is at least the largest distance `inner` can return between two present values; with a
+/// smaller penalty, a BK-tree using it may miss some matches.
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "rkyv-support",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+#[derive(Debug, Clone)]
+pub struct NullableDistance<D> {
+    pub inner: D,
+    pub penalty: isize,
+}
+
+impl<T, D: Distance<T>> Distance<Option<T>> for NullableDistance<D> {
+    fn distance(&self, a: &Option<T>, b: &Option<T>) -> isize {
+        match (a, b) {
+            (Some(a), Some(b)) => self.inner.distance(a, b),
+            (None, None) => 0,
+            _ => self.penalty,
+        }
+    }
+}
+
+/// Distance function over references and smart pointers, applying `D` to their targets
+///
+/// This turns e.g. a `Distance<str>` into a distance function over `&str`, `String` or
+/// `Box<str>`. A blanket implementation for every `D` is not possible, since it would overlap
+/// with implementations of metrics which are generic over their input, such as
+/// [`LevenshteinDistance`] over `AsRef<str>`.
+///
+/// ```rust
+/// use bktree::*;
+///
+/// struct Length;
+///
+/// impl Distance<str> for Length {
+///     fn distance(&self, a: &str, b: &str) -> isize {
+///         (a.len() as isize - b.len() as isize).abs()
+///     }
+/// }
+///
+/// let mut bk = BkTree::new(DerefDistance(Length));
+/// bk.insert_all(vec!["a", "bcd"]);
+/// assert_eq!(bk.find("xy", 1), [(&"a", 1), (&"bcd", 1)]);
+/// ```
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "rkyv-support",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+#[derive(Debug, Clone)]
+pub struct DerefDistance<D>(pub D);
+
+impl<T: std::ops::Deref, D: Distance<T::Target>> Distance<T> for DerefDistance<D> {
+    fn distance(&self, a: &T, b: &T) -> isize {
+        self.0.distance(a, b)
+    }
+
+    fn bounded_max(&self) -> Option<isize> {
+        self.0.bounded_max()
+    }
+}
+
+/// A distance function which needs mutable access to itself, e.g. to update a cache
+///
+/// Every [`Distance`] is a `DistanceMut`. Such metrics are used through
+/// [`BkTreeMut`](crate::BkTreeMut).
+pub trait DistanceMut<T: ?Sized> {
+    fn distance(&mut self, a: &T, b: &T) -> isize;
+}
+
+impl<T: ?Sized, D: Distance<T>> DistanceMut<T> for D {
+    fn distance(&mut self, a: &T, b: &T) -> isize {
+        Distance::distance(self, a, b)
+    }
 }
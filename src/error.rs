@@ -0,0 +1,46 @@
+use std::fmt;
+
+/// Error returned by fallible BK-tree operations
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BkError {
+    /// The distance function returned a value a metric cannot produce
+    NonMetric { distance: isize },
+    /// An edge does not match the distance between its parent and an element below it
+    CorruptStructure { edge: isize, distance: isize },
+    /// An edge is not positive or is shared with a sibling
+    InvalidEdge { edge: isize },
+    /// Two vectors of different dimensions were compared
+    DimensionMismatch { expected: usize, found: usize },
+}
+
+impl fmt::Display for BkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BkError::NonMetric { distance } => {
+                write!(
+                    f,
+                    "distance function returned invalid distance {}",
+                    distance
+                )
+            }
+            BkError::CorruptStructure { edge, distance } => write!(
+                f,
+                "invalid edge of distance {} to an element at distance {}",
+                edge, distance
+            ),
+            BkError::InvalidEdge { edge } => write!(
+                f,
+                "edge of distance {} is not positive or is shared with a sibling",
+                edge
+            ),
+            BkError::DimensionMismatch { expected, found } => write!(
+                f,
+                "expected a vector of dimension {}, found {}",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BkError {}
@@ -0,0 +1,139 @@
+use crate::{Distance, LevenshteinDistance, Node};
+use num::PrimInt;
+use std::collections::VecDeque;
+
+/// An immutable BK-tree, obtained through [`BkTree::freeze`](crate::BkTree::freeze)
+///
+/// Nodes are laid out contiguously in breadth-first order, which makes searching more cache
+/// friendly. Being immutable, it can be cheaply shared between threads behind an `Arc`.
+///
+/// Edge distances are stored as `E`, so a narrower integer type such as `u8` shrinks the BK-tree
+/// when the distance function never exceeds its range, see
+/// [`BkTree::freeze_with_edges`](crate::BkTree::freeze_with_edges).
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[derive(Debug, Clone)]
+pub struct FrozenBkTree<T, D = LevenshteinDistance, E = isize> {
+    words: Vec<T>,
+    /// Edges to the children of node `i` are `edges[offsets[i]..offsets[i + 1]]`
+    offsets: Vec<usize>,
+    /// Distances of the edges, in breadth-first order: edge `k` leads to node `k + 1`
+    edges: Vec<E>,
+    dist: D,
+}
+
+impl<T, D, E: PrimInt> FrozenBkTree<T, D, E> {
+    /// Lay out the nodes below `root`, panicking if an edge distance is out of the range of `E`
+    pub(crate) fn from_root(root: Option<Node<T>>, dist: D) -> Self {
+        let mut words = Vec::new();
+        let mut offsets = vec![0];
+        let mut edges = Vec::new();
+
+        let mut queue: VecDeque<Node<T>> = root.into_iter().collect();
+        while let Some(node) = queue.pop_front() {
+            for (arc, child) in node.children {
+                edges.push(E::from(arc).expect("edge distance out of range of the edge type"));
+                queue.push_back(child);
+            }
+            offsets.push(edges.len());
+            words.push(node.word);
+        }
+
+        Self {
+            words,
+            offsets,
+            edges,
+            dist,
+        }
+    }
+
+    /// Iterate over the children of node `i` along with the distances of their edges
+    fn children(&self, i: usize) -> impl Iterator<Item = (isize, usize)> + '_ {
+        let (start, end) = (self.offsets[i], self.offsets[i + 1]);
+        self.edges[start..end]
+            .iter()
+            .zip(start + 1..end + 1)
+            .map(|(arc, child)| (arc.to_isize().unwrap_or(isize::MAX), child))
+    }
+
+    /// Estimate the memory used by the BK-tree, in bytes
+    ///
+    /// This is the size of the BK-tree itself plus the allocated capacity of its element, offset
+    /// and edge vectors. Heap memory owned by the elements themselves is not counted.
+    pub fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.words.capacity() * std::mem::size_of::<T>()
+            + self.offsets.capacity() * std::mem::size_of::<usize>()
+            + self.edges.capacity() * std::mem::size_of::<E>()
+    }
+
+    /// Create an iterator over references of BK-tree elements, in no particular order
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.words.iter()
+    }
+}
+
+impl<T, D, E> FrozenBkTree<T, D, E>
+where
+    D: Distance<T>,
+    E: PrimInt,
+{
+    /// Find the closest elements to a given value present in the BK-tree
+    ///
+    /// Returns pairs of element references and distances
+    pub fn find(&self, val: T, max_dist: isize) -> Vec<(&T, isize)> {
+        let mut found = Vec::new();
+        if self.words.is_empty() {
+            return found;
+        }
+
+        let mut candidates = VecDeque::new();
+        candidates.push_back(0);
+
+        while let Some(i) = candidates.pop_front() {
+            let distance = self.dist.distance(&self.words[i], &val);
+            if distance <= max_dist {
+                found.push((&self.words[i], distance));
+            }
+
+            candidates.extend(
+                self.children(i)
+                    .filter(|(arc, _)| (*arc - distance).abs() <= max_dist)
+                    .map(|(_, child)| child),
+            );
+        }
+        found
+    }
+
+    /// Find the closest element to a given value present in the BK-tree
+    ///
+    /// Returns the element reference and its distance, or `None` if the BK-tree is empty
+    pub fn find_nearest(&self, val: T) -> Option<(&T, isize)> {
+        let mut best: Option<(&T, isize)> = None;
+        let mut candidates: Vec<usize> = if self.words.is_empty() {
+            Vec::new()
+        } else {
+            vec![0]
+        };
+
+        while let Some(i) = candidates.pop() {
+            let distance = self.dist.distance(&self.words[i], &val);
+            if best.is_none_or(|(_, best_dist)| distance < best_dist) {
+                best = Some((&self.words[i], distance));
+                if distance == 0 {
+                    break;
+                }
+            }
+
+            let bound = best.map_or(isize::MAX, |(_, best_dist)| best_dist);
+            candidates.extend(
+                self.children(i)
+                    .filter(|(arc, _)| (*arc - distance).abs() < bound)
+                    .map(|(_, child)| child),
+            );
+        }
+        best
+    }
+}
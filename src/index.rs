@@ -0,0 +1,49 @@
+use crate::{BkTree, Distance};
+
+/// Common interface of indexes answering "close" match queries, allowing code to be written once
+/// against several backends
+pub trait NearestNeighborIndex<T> {
+    /// Insert a new element in the index
+    fn insert(&mut self, val: T);
+
+    /// Find the closest elements to a given value present in the index
+    ///
+    /// Returns pairs of element references and distances
+    fn find(&self, val: T, max_dist: isize) -> Vec<(&T, isize)>;
+
+    /// Find the closest element to a given value present in the index
+    fn find_nearest(&self, val: T) -> Option<(&T, isize)>;
+
+    /// Count the elements of the index
+    fn len(&self) -> usize;
+
+    /// Check whether the index holds no element
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T, D> NearestNeighborIndex<T> for BkTree<T, D>
+where
+    D: Distance<T>,
+{
+    fn insert(&mut self, val: T) {
+        BkTree::insert(self, val);
+    }
+
+    fn find(&self, val: T, max_dist: isize) -> Vec<(&T, isize)> {
+        BkTree::find(self, val, max_dist)
+    }
+
+    fn find_nearest(&self, val: T) -> Option<(&T, isize)> {
+        BkTree::find_nearest(self, val)
+    }
+
+    fn len(&self) -> usize {
+        BkTree::len(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        BkTree::is_empty(self)
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{BkMap, Distance, LevenshteinDistance};
+
+/// A BK-tree remembering the insertion index of each element
+///
+/// Every call to [`insert`](Self::insert) consumes an index, even when the element is dropped
+/// as a duplicate, so that indices match positions in the inserted sequence.
+///
+/// ```rust
+/// use bktree::*;
+///
+/// let mut bk = IndexedBkTree::new(LevenshteinDistance);
+/// bk.insert_all(vec!["book", "cake", "boo"]);
+///
+/// assert_eq!(bk.find_indexed("bo", 1), [(2, 1)]);
+/// ```
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[derive(Clone)]
+pub struct IndexedBkTree<T, D = LevenshteinDistance> {
+    map: BkMap<T, usize, D>,
+    next: usize,
+}
+
+impl<T, D> IndexedBkTree<T, D>
+where
+    D: Distance<T>,
+{
+    /// Create a new indexed BK-tree with a given distance function
+    pub fn new(dist: D) -> Self {
+        Self {
+            map: BkMap::new(dist),
+            next: 0,
+        }
+    }
+
+    /// Insert every element from a given iterator in the indexed BK-tree
+    pub fn insert_all<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for i in iter {
+            self.insert(i);
+        }
+    }
+
+    /// Insert a new element in the indexed BK-tree
+    ///
+    /// Returns the insertion index consumed by this element
+    pub fn insert(&mut self, val: T) -> usize {
+        let index = self.next;
+        self.next += 1;
+        self.map.insert(val, index);
+        index
+    }
+
+    /// Find the closest elements to a given value present in the indexed BK-tree
+    ///
+    /// Returns pairs of insertion indices and distances
+    pub fn find_indexed(&self, val: T, max_dist: isize) -> Vec<(usize, isize)> {
+        self.map
+            .find(&val, max_dist)
+            .into_iter()
+            .map(|(_, index, dist)| (*index, dist))
+            .collect()
+    }
+
+    /// Find the closest elements to a given value present in the indexed BK-tree
+    ///
+    /// Returns triples of element references, insertion indices and distances
+    pub fn find(&self, val: T, max_dist: isize) -> Vec<(&T, usize, isize)> {
+        self.map
+            .find(&val, max_dist)
+            .into_iter()
+            .map(|(word, index, dist)| (word, *index, dist))
+            .collect()
+    }
+}
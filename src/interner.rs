@@ -0,0 +1,107 @@
+use crate::{BkTree, Distance, LevenshteinDistance};
+
+/// Compact identifier of a string stored in an [`Interner`]
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SymbolId(u32);
+
+/// A distance function over [`SymbolId`]s which owns the interned strings and resolves them
+/// before delegating to an inner string distance
+///
+/// All strings are stored back to back in a single buffer, so nodes of the BK-tree only hold a
+/// `u32` each.
+///
+/// ```rust
+/// use bktree::*;
+///
+/// let mut bk: InternedBkTree = BkTree::new(Interner::new(LevenshteinDistance));
+/// bk.insert_str("book");
+/// bk.insert_str("boo");
+/// bk.insert_str("cake");
+///
+/// let words: Vec<&str> = bk.find_str("bo", 1).into_iter().map(|(w, _)| w).collect();
+/// assert_eq!(words, ["boo"]);
+/// ```
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[derive(Debug, Clone)]
+pub struct Interner<D = LevenshteinDistance> {
+    buffer: String,
+    ends: Vec<usize>,
+    dist: D,
+}
+
+/// A BK-tree storing interned strings
+pub type InternedBkTree<D = LevenshteinDistance> = BkTree<SymbolId, Interner<D>>;
+
+impl<D> Interner<D> {
+    /// Create an empty interner delegating to a given string distance function
+    pub fn new(dist: D) -> Self {
+        Self {
+            buffer: String::new(),
+            ends: Vec::new(),
+            dist,
+        }
+    }
+
+    /// Get the string behind a symbol
+    ///
+    /// Panics if the symbol was not created by this interner.
+    pub fn resolve(&self, id: SymbolId) -> &str {
+        let i = id.0 as usize;
+        let start = if i == 0 { 0 } else { self.ends[i - 1] };
+        &self.buffer[start..self.ends[i]]
+    }
+
+    fn intern(&mut self, val: &str) -> SymbolId {
+        let id = SymbolId(self.ends.len() as u32);
+        self.buffer.push_str(val);
+        self.ends.push(self.buffer.len());
+        id
+    }
+}
+
+impl<D: Distance<str>> Distance<SymbolId> for Interner<D> {
+    fn distance(&self, a: &SymbolId, b: &SymbolId) -> isize {
+        self.dist.distance(self.resolve(*a), self.resolve(*b))
+    }
+}
+
+impl<D: Distance<str>> BkTree<SymbolId, Interner<D>> {
+    /// Intern a string and insert it in the BK-tree
+    ///
+    /// Returns the symbol of the newly stored string, or of the existing one if an equivalent
+    /// string was already present.
+    pub fn insert_str(&mut self, val: &str) -> SymbolId {
+        if let Some((id, _)) = self.find_by(|id| self.distance_to_str(*id, val), 0).first() {
+            return **id;
+        }
+        let id = self.dist.intern(val);
+        self.insert(id);
+        id
+    }
+
+    /// Find the closest strings to a given value present in the BK-tree
+    ///
+    /// Returns pairs of resolved strings and distances
+    pub fn find_str(&self, val: &str, max_dist: isize) -> Vec<(&str, isize)> {
+        self.find_by(|id| self.distance_to_str(*id, val), max_dist)
+            .into_iter()
+            .map(|(id, dist)| (self.dist.resolve(*id), dist))
+            .collect()
+    }
+
+    /// Get the string behind a symbol stored in this BK-tree
+    pub fn resolve(&self, id: SymbolId) -> &str {
+        self.dist.resolve(id)
+    }
+
+    fn distance_to_str(&self, id: SymbolId, val: &str) -> isize {
+        self.dist.dist.distance(self.dist.resolve(id), val)
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{BkTree, Distance, Iter, LevenshteinDistance};
+
+/// Distance over values which compares keys derived from them
+#[derive(Clone)]
+struct KeyedDistance<F, D> {
+    key_fn: F,
+    dist: D,
+}
+
+impl<T, K, F, D> Distance<T> for KeyedDistance<F, D>
+where
+    F: Fn(&T) -> K,
+    D: Distance<K>,
+{
+    fn distance(&self, a: &T, b: &T) -> isize {
+        self.dist.distance(&(self.key_fn)(a), &(self.key_fn)(b))
+    }
+}
+
+/// A BK-tree storing values indexed by a key derived from each of them
+///
+/// Keys are computed by `key_fn` whenever a distance is needed, so it should be cheap.
+///
+/// ```rust
+/// use bktree::*;
+///
+/// struct Record {
+///     name: String,
+///     age: u32,
+/// }
+///
+/// let mut bk = KeyedBkTree::new(|r: &Record| r.name.to_lowercase(), LevenshteinDistance);
+/// bk.insert(Record { name: "Alice".into(), age: 30 });
+/// bk.insert(Record { name: "Bob".into(), age: 40 });
+///
+/// let found = bk.find(&"alyce".to_string(), 1);
+/// assert_eq!(found.len(), 1);
+/// assert_eq!(found[0].0.age, 30);
+/// ```
+#[derive(Clone)]
+pub struct KeyedBkTree<T, F, D = LevenshteinDistance> {
+    tree: BkTree<T, KeyedDistance<F, D>>,
+}
+
+impl<T, K, F, D> KeyedBkTree<T, F, D>
+where
+    F: Fn(&T) -> K,
+    D: Distance<K>,
+{
+    /// Create a new keyed BK-tree with a given key function and distance function over keys
+    pub fn new(key_fn: F, dist: D) -> Self {
+        Self {
+            tree: BkTree::new(KeyedDistance { key_fn, dist }),
+        }
+    }
+
+    /// Insert a new value in the keyed BK-tree
+    ///
+    /// Returns `false` if a value whose key is at distance 0 was already present, in which case
+    /// the keyed BK-tree is left unchanged
+    pub fn insert(&mut self, val: T) -> bool {
+        self.tree.insert(val)
+    }
+
+    /// Find the values whose keys are closest to a given key
+    ///
+    /// Returns pairs of value references and distances
+    pub fn find(&self, key: &K, max_dist: isize) -> Vec<(&T, isize)> {
+        let KeyedDistance { key_fn, dist } = &self.tree.dist;
+        self.tree
+            .find_by(|val| dist.distance(&key_fn(val), key), max_dist)
+    }
+
+    /// Count the values of the keyed BK-tree
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Check whether the keyed BK-tree holds no value
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Create an iterator over references of the stored values, in no particular order
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.tree.iter()
+    }
+}
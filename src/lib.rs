@@ -29,28 +29,447 @@ pub mod distance;
 
 pub use distance::*;
 
+#[cfg(feature = "serde-support")]
+pub mod compact;
+
+#[cfg(feature = "serde-support")]
+pub mod bfs;
+
+/// Deterministic datasets for tests and benchmarks
+#[cfg(feature = "test-util")]
+pub mod test_util;
+
+/// Zero-copy search of archived BK-trees
+#[cfg(feature = "rkyv-support")]
+pub mod archive;
+
+/// BK-tree variant evicting its least recently used elements
+pub mod bounded;
+
+pub use bounded::*;
+
+/// Immutable read-optimized BK-tree
+pub mod frozen;
+
+pub use frozen::*;
+
+/// Change tracking for incremental serialization
+mod delta;
+
+/// Duplicate detection by canonical form at insertion
+mod dedup;
+
+/// Seeded pseudo-random number generation
+mod rng;
+
+use dedup::{Canonical, CanonicalDedup};
+use delta::DirtySubtrees;
+use rng::SplitMix64;
+
+/// Error type of fallible operations
+pub mod error;
+
+pub use error::*;
+
+/// Common interface of nearest neighbor indexes
+pub mod index;
+
+pub use index::*;
+
+/// BK-tree variant remembering insertion indices
+pub mod indexed;
+
+pub use indexed::*;
+
+/// String interning to store compact symbols in the BK-tree
+pub mod interner;
+
+pub use interner::*;
+
+/// BK-tree variant indexing values by a derived key
+pub mod keyed;
+
+pub use keyed::*;
+
+/// BK-tree variant associating values with keys
+pub mod map;
+
+pub use map::*;
+
+/// Configuration of BK-trees before their construction
+pub mod builder;
+
+pub use builder::*;
+
+/// BK-tree variant for distance functions with mutable state
+pub mod mutable;
+
+pub use mutable::*;
+
+/// Distance functions over streams of tokens
+pub mod streaming;
+
+pub use streaming::*;
+
+/// Thread-safe BK-tree for concurrent searches
+#[cfg(feature = "std")]
+pub mod sync;
+#[cfg(feature = "std")]
+pub use sync::*;
+
+/// Searches computing distances on a pool of workers
+#[cfg(feature = "pool")]
+pub mod pool;
+#[cfg(feature = "pool")]
+pub use pool::*;
+
+use std::borrow::{Borrow, Cow};
+use std::cmp::{Ordering, Reverse};
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet, TryReserveError};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+#[cfg(feature = "std")]
+use std::{
+    fmt::Display,
+    io::{self, Write},
+};
+
 #[cfg(feature = "serde-support")]
 extern crate serde;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug)]
 #[cfg_attr(
     feature = "serde-support",
     derive(serde::Serialize, serde::Deserialize)
 )]
+#[cfg_attr(
+    feature = "rkyv-support",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    rkyv(
+        serialize_bounds(
+            __S: rkyv::ser::Writer + rkyv::ser::Allocator,
+            __S::Error: rkyv::rancor::Source,
+        ),
+        deserialize_bounds(__D::Error: rkyv::rancor::Source),
+        bytecheck(bounds(
+            __C: rkyv::validation::ArchiveContext,
+            __C::Error: rkyv::rancor::Source,
+        ))
+    )
+)]
 struct Node<T> {
     word: T,
+    #[cfg_attr(feature = "rkyv-support", rkyv(omit_bounds))]
     children: Vec<(isize, Node<T>)>,
+    /// Positions of the children by edge, only built past the BK-tree's index threshold, and
+    /// boxed to keep the many nodes without an index small
+    #[allow(clippy::box_collection)]
+    #[cfg_attr(feature = "serde-support", serde(skip))]
+    #[cfg_attr(feature = "rkyv-support", rkyv(with = rkyv::with::Skip))]
+    index: Option<Box<HashMap<isize, usize>>>,
+}
+
+/// Number of children past which a node indexes them by edge, see
+/// [`BkTree::with_index_threshold`]
+pub const DEFAULT_INDEX_THRESHOLD: usize = 32;
+
+#[cfg(feature = "serde-support")]
+fn default_index_threshold() -> usize {
+    DEFAULT_INDEX_THRESHOLD
+}
+
+/// Settings of an insertion below a node, see [`Node::insert_node`]
+#[derive(Clone, Copy)]
+pub(crate) struct Insert {
+    /// Keep elements at distance 0 of a stored one
+    pub(crate) multiset: bool,
+    /// Number of children past which a node indexes them by edge
+    pub(crate) threshold: usize,
+    /// Distance from the node to the element, if already known
+    pub(crate) hint: Option<isize>,
+    /// Report allocation failures instead of aborting
+    pub(crate) fallible: bool,
+}
+
+/// Outcome of [`Node::insert_node`]
+pub(crate) enum Insertion<'a, T> {
+    /// The element was added as the given node, in the subtree below the given edge of the
+    /// node it was inserted below
+    Added(&'a mut Node<T>, isize),
+    /// The element was dropped, as the given node holds an element at distance 0
+    Present(&'a mut Node<T>),
+    /// The element was dropped, as room for it could not be allocated
+    Failed(TryReserveError),
+}
+
+impl<T> Node<T> {
+    fn new(word: T) -> Self {
+        Self::with_children(word, Vec::new())
+    }
+
+    fn with_children(word: T, children: Vec<(isize, Node<T>)>) -> Self {
+        Node {
+            word,
+            children,
+            index: None,
+        }
+    }
+
+    /// Get the position of the child below a given edge
+    fn child_position(&self, edge: isize) -> Option<usize> {
+        match self.index {
+            Some(ref index) => index.get(&edge).copied(),
+            None => self.children.iter().position(|(dist, _)| *dist == edge),
+        }
+    }
+
+    /// Add a child below a given edge, indexing the children once there are more than
+    /// `threshold` of them
+    fn push_child(&mut self, edge: isize, child: Node<T>, threshold: usize) {
+        self.children.push((edge, child));
+        match self.index {
+            Some(ref mut index) => {
+                index.entry(edge).or_insert(self.children.len() - 1);
+            }
+            None if self.children.len() > threshold => self.reindex(threshold),
+            None => {}
+        }
+    }
+
+    /// Add a child below a given edge like [`push_child`](Self::push_child), reporting
+    /// allocation failures instead of aborting
+    fn try_push_child(
+        &mut self,
+        edge: isize,
+        child: Node<T>,
+        threshold: usize,
+    ) -> Result<(), TryReserveError> {
+        self.children.try_reserve(1)?;
+        match self.index {
+            Some(ref mut index) => index.try_reserve(1)?,
+            None if self.children.len() >= threshold => {
+                let mut index = HashMap::new();
+                index.try_reserve(self.children.len() + 1)?;
+                for (pos, (edge, _)) in self.children.iter().enumerate() {
+                    index.entry(*edge).or_insert(pos);
+                }
+                self.index = Some(Box::new(index));
+            }
+            None => {}
+        }
+        self.push_child(edge, child, threshold);
+        Ok(())
+    }
+
+    /// Insert a new element below this node, computing distances from stored elements to it
+    /// with `distance`
+    ///
+    /// This is the descent shared by every insertion path: it follows the edge of the
+    /// element's distance from each node until none leads further, and adds the element there.
+    pub(crate) fn insert_node<F>(
+        &mut self,
+        word: T,
+        mut distance: F,
+        opts: Insert,
+    ) -> Insertion<'_, T>
+    where
+        F: FnMut(&T, &T) -> isize,
+    {
+        let mut hint = opts.hint;
+        let mut u = self;
+        let mut root_edge = None;
+        loop {
+            let k = match hint.take() {
+                Some(k) => {
+                    debug_assert_eq!(k, distance(&u.word, &word));
+                    k
+                }
+                None => distance(&u.word, &word),
+            };
+            if k == 0 && !opts.multiset {
+                return Insertion::Present(u);
+            }
+            let root_edge = *root_edge.get_or_insert(k);
+
+            // Elements at distance 0 of a multiset element become new children
+            match u.child_position(k).filter(|_| k != 0) {
+                Some(pos) => u = &mut u.children[pos].1,
+                None if opts.fallible => {
+                    return match u.try_push_child(k, Node::new(word), opts.threshold) {
+                        Ok(()) => Insertion::Added(u.last_child(), root_edge),
+                        Err(e) => Insertion::Failed(e),
+                    };
+                }
+                None => {
+                    u.push_child(k, Node::new(word), opts.threshold);
+                    return Insertion::Added(u.last_child(), root_edge);
+                }
+            }
+        }
+    }
+
+    fn last_child(&mut self) -> &mut Node<T> {
+        let last = self.children.len() - 1;
+        &mut self.children[last].1
+    }
+
+    /// Rebuild the index of the children after they were changed
+    fn reindex(&mut self, threshold: usize) {
+        self.index = if self.children.len() > threshold {
+            let mut index = HashMap::with_capacity(self.children.len());
+            for (pos, (edge, _)) in self.children.iter().enumerate() {
+                index.entry(*edge).or_insert(pos);
+            }
+            Some(Box::new(index))
+        } else {
+            None
+        };
+    }
+
+    /// Get the number of edges on the longest path from this node to a leaf
+    fn depth(&self) -> usize {
+        let mut depth = 0;
+        let mut queue: Vec<(usize, &Node<T>)> = vec![(0, self)];
+        while let Some((d, n)) = queue.pop() {
+            depth = depth.max(d);
+            queue.extend(n.children.iter().map(|(_, child)| (d + 1, child)));
+        }
+        depth
+    }
+}
+
+impl<T: Clone> Clone for Node<T> {
+    fn clone(&self) -> Self {
+        Node {
+            word: self.word.clone(),
+            children: self.children.clone(),
+            index: self.index.clone(),
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.word.clone_from(&source.word);
+        // Tuples clone wholesale, so recurse by hand to reuse the allocations of matching children
+        self.children.truncate(source.children.len());
+        let reused = self.children.len();
+        for ((arc, child), (source_arc, source_child)) in
+            self.children.iter_mut().zip(&source.children)
+        {
+            *arc = *source_arc;
+            child.clone_from(source_child);
+        }
+        self.children
+            .extend(source.children[reused..].iter().cloned());
+        self.index.clone_from(&source.index);
+    }
+}
+
+impl<T: Eq> Eq for Node<T> {}
+
+impl<T: Hash> Hash for Node<T> {
+    /// Children are hashed in order of edge distance and then of their own hash, so that the
+    /// hash does not depend on the order in which they were inserted, even when several of
+    /// them share an edge in a multiset BK-tree
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.word.hash(state);
+        let mut children: Vec<(isize, u64)> = self
+            .children
+            .iter()
+            .map(|(dist, child)| {
+                let mut hasher = DefaultHasher::new();
+                child.hash(&mut hasher);
+                (*dist, hasher.finish())
+            })
+            .collect();
+        children.sort_unstable();
+        children.hash(state);
+    }
+}
+
+impl<T: PartialEq> PartialEq for Node<T> {
+    /// Two nodes are equal if they hold equal words and equal children under the same edge
+    /// distances, regardless of the order in which those children were inserted
+    ///
+    /// Children are matched one to one, since a multiset BK-tree may hold several children
+    /// under the same edge.
+    fn eq(&self, other: &Self) -> bool {
+        if self.word != other.word || self.children.len() != other.children.len() {
+            return false;
+        }
+        let mut matched = vec![false; other.children.len()];
+        self.children.iter().all(|(dist, node)| {
+            let pos = other.children.iter().zip(&matched).position(
+                |((other_dist, other_node), &used)| {
+                    !used && dist == other_dist && node == other_node
+                },
+            );
+            pos.map(|pos| matched[pos] = true).is_some()
+        })
+    }
+}
+
+/// Explicit description of a BK-tree node, used to build a BK-tree with [`BkTree::from_root`]
+#[derive(Debug, Clone)]
+pub struct NodeSpec<T> {
+    pub word: T,
+    /// Children along with the distance of their edge
+    pub children: Vec<(isize, NodeSpec<T>)>,
 }
 
+/// Function mapping elements to a canonical form, see [`BkTree::with_canonical_dedup`]
+pub type Canonicalize<T> = Arc<dyn Fn(&T) -> T + Send + Sync>;
+
 /// A BK-tree datastructure
 ///
+/// With the `serde-support` feature, a BK-tree can be serialized when both its elements and its
+/// distance function can, and the state of the distance function, e.g. the scale of a
+/// [`DiceDistance`], is stored along with the elements so that a deserialized BK-tree searches
+/// the same way.
 #[cfg_attr(
     feature = "serde-support",
     derive(serde::Serialize, serde::Deserialize)
 )]
+#[cfg_attr(
+    feature = "rkyv-support",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
 pub struct BkTree<T, D = distance::LevenshteinDistance> {
     root: Option<Node<T>>,
     dist: D,
+    #[cfg_attr(feature = "serde-support", serde(skip))]
+    #[cfg_attr(feature = "rkyv-support", rkyv(with = rkyv::with::Skip))]
+    dirty: DirtySubtrees,
+    #[cfg_attr(feature = "serde-support", serde(default))]
+    multiset: bool,
+    #[cfg_attr(feature = "serde-support", serde(default = "default_index_threshold"))]
+    index_threshold: usize,
+    /// Canonical forms of the elements, used to detect duplicates at insertion
+    #[cfg_attr(feature = "serde-support", serde(skip, default = "Option::default"))]
+    #[cfg_attr(feature = "rkyv-support", rkyv(with = rkyv::with::Skip))]
+    canonical_dedup: Option<CanonicalDedup<T>>,
+}
+
+impl<T, D> BkTree<T, D> {
+    pub(crate) fn from_parts(root: Option<Node<T>>, dist: D) -> Self {
+        Self {
+            root,
+            dist,
+            dirty: DirtySubtrees::new(),
+            multiset: false,
+            index_threshold: DEFAULT_INDEX_THRESHOLD,
+            canonical_dedup: None,
+        }
+    }
+
+    /// Record the canonical forms of the elements again, after some of them were removed
+    pub(crate) fn reset_canonical_keys(&mut self) {
+        if let Some(ref mut dedup) = self.canonical_dedup {
+            dedup.reset(Iter {
+                queue: self.root.iter().collect(),
+            });
+        }
+    }
 }
 
 impl<T, D> BkTree<T, D>
@@ -59,174 +478,4327 @@ where
 {
     /// Create a new BK-tree with a given distance function
     pub fn new(dist: D) -> Self {
-        Self { root: None, dist }
+        Self::from_parts(None, dist)
     }
 
-    /// Insert every element from a given iterator in the BK-tree
-    pub fn insert_all<I: IntoIterator<Item = T>>(&mut self, iter: I) {
-        for i in iter {
-            self.insert(i);
+    /// Create a new BK-tree with a given distance function, keeping elements at distance 0 of
+    /// an existing one if `multiset` is set
+    ///
+    /// In a multiset BK-tree, inserting an element at distance 0 of a stored one adds it as a
+    /// separate entry below the stored one, so it is counted by [`len`](Self::len) and yielded
+    /// by iterators and searches once per insertion.
+    ///
+    /// ```rust
+    /// use bktree::*;
+    ///
+    /// let mut bk = BkTree::with_multiset(LevenshteinDistance, true);
+    /// bk.insert_all(vec!["book", "book", "boo"]);
+    /// assert_eq!(bk.len(), 3);
+    /// assert_eq!(bk.find("book", 0), [(&"book", 0), (&"book", 0)]);
+    /// ```
+    pub fn with_multiset(dist: D, multiset: bool) -> Self {
+        let mut bk = Self::new(dist);
+        bk.multiset = multiset;
+        bk
+    }
+
+    /// Create a new BK-tree with a given distance function, indexing the children of nodes by
+    /// edge once they have more than `threshold` children
+    ///
+    /// Nodes with few children are searched linearly for the child below a given edge, which is
+    /// the fastest for small fanouts, while the index keeps insertions fast below nodes with
+    /// many children. The index does not change the order in which children are visited. Nodes
+    /// of deserialized BK-trees are indexed again as they grow. The default threshold is
+    /// [`DEFAULT_INDEX_THRESHOLD`].
+    pub fn with_index_threshold(dist: D, threshold: usize) -> Self {
+        let mut bk = Self::new(dist);
+        bk.index_threshold = threshold;
+        bk
+    }
+
+    /// Create a new BK-tree with a given distance function, treating elements with the same
+    /// image by `canonicalize` as duplicates at insertion
+    ///
+    /// This lets e.g. "Book" and "book" collapse into a single element, the first one inserted,
+    /// while searches keep comparing the stored elements as they are: `find("book", 0)` does
+    /// not match a stored "Book". The canonical forms of the stored elements are kept in a hash
+    /// set, so a duplicate is found whatever its distance to the inserted element, at the cost
+    /// of one call to `canonicalize` per insertion and of the memory of the canonical forms.
+    /// This applies to every insertion, including [`try_insert`](Self::try_insert) and
+    /// [`insert_if_absent`](Self::insert_if_absent), and even to
+    /// [multiset](Self::with_multiset) BK-trees. Removals record the canonical forms of the
+    /// remaining elements again. The canonicalization is not serialized.
+    ///
+    /// ```rust
+    /// use bktree::*;
+    ///
+    /// let mut bk = BkTree::with_canonical_dedup(LevenshteinDistance, |w: &String| {
+    ///     w.to_lowercase()
+    /// });
+    /// assert!(bk.insert("Book".to_string()));
+    /// assert!(!bk.insert("BOOK".to_string()));
+    /// assert_eq!(bk.len(), 1);
+    /// ```
+    pub fn with_canonical_dedup<F>(dist: D, canonicalize: F) -> Self
+    where
+        T: Eq + Hash,
+        F: Fn(&T) -> T + Send + Sync + 'static,
+    {
+        let mut bk = Self::new(dist);
+        bk.canonical_dedup = Some(CanonicalDedup::new(Arc::new(canonicalize)));
+        bk
+    }
+
+    /// Check `val` against the canonical forms of the stored elements
+    fn check_canonical(&self, val: &T) -> Canonical<T> {
+        match self.canonical_dedup {
+            None => Canonical::Untracked,
+            Some(ref dedup) => dedup
+                .check(val)
+                .map_or(Canonical::Duplicate, Canonical::New),
         }
     }
 
-    /// Insert a new element in the BK-tree
-    pub fn insert(&mut self, val: T) {
-        match self.root {
-            None => {
-                self.root = Some(Node {
-                    word: val,
-                    children: Vec::new(),
-                })
-            }
-            Some(ref mut root_node) => {
-                let mut u = root_node;
-                loop {
-                    let k = self.dist.distance(&u.word, &val);
-                    if k == 0 {
-                        return;
-                    }
+    /// Record the canonical form of an inserted element
+    fn record_canonical(&mut self, canonical: Canonical<T>) {
+        if let (Canonical::New(key), Some(dedup)) = (canonical, self.canonical_dedup.as_mut()) {
+            dedup.record(key);
+        }
+    }
 
-                    let v = u.children.iter().position(|(dist, _)| *dist == k);
-                    match v {
-                        None => {
-                            u.children.push((
-                                k,
-                                Node {
-                                    word: val,
-                                    children: Vec::new(),
-                                },
-                            ));
-                            return;
-                        }
-                        Some(pos) => {
-                            let (_, ref mut vnode) = u.children[pos];
-                            u = vnode;
-                        }
-                    }
-                }
+    /// Create a BK-tree with a given distance function from sorted elements
+    ///
+    /// Elements are inserted in bisection order: the middle element first, then the middles of
+    /// both halves and so on, so that the shape of the tree does not depend on the order the
+    /// input was produced in. The input is assumed to be sorted and deduplicated.
+    ///
+    /// This does not guarantee balance, and no insertion order can: the depth of a BK-tree
+    /// depends on how distances between elements are distributed, e.g. elements which are all
+    /// at the same distance from one another always form a chain. Bisection helps when sorted
+    /// neighbors are close to each other, which makes in-order insertion build long chains.
+    /// [`rebuild`](Self::rebuild) trades more distance computations for a balance-aware
+    /// construction, with no guarantee either.
+    pub fn from_sorted_unique(dist: D, sorted: Vec<T>) -> Self {
+        let mut words: Vec<Option<T>> = sorted.into_iter().map(Some).collect();
+        let mut bk = Self::new(dist);
+        let mut ranges = std::collections::VecDeque::new();
+        ranges.push_back(0..words.len());
+        while let Some(range) = ranges.pop_front() {
+            if range.is_empty() {
+                continue;
+            }
+            let mid = range.start + range.len() / 2;
+            if let Some(word) = words[mid].take() {
+                bk.insert(word);
             }
+            ranges.push_back(range.start..mid);
+            ranges.push_back(mid + 1..range.end);
         }
+        bk
     }
 
-    /// Find the closest elements to a given value present in the BK-tree
+    /// Create a BK-tree with a given distance function from an explicit node structure
     ///
-    /// Returns pairs of element references and distances
-    pub fn find(&self, val: T, max_dist: isize) -> Vec<(&T, isize)> {
-        match self.root {
-            None => Vec::new(),
-            Some(ref root) => {
-                let mut found = Vec::new();
-
-                let mut candidates: std::collections::VecDeque<&Node<T>> =
-                    std::collections::VecDeque::new();
-                candidates.push_back(root);
-
-                while let Some(n) = candidates.pop_front() {
-                    let distance = self.dist.distance(&n.word, &val);
-                    if distance <= max_dist {
-                        found.push((&n.word, distance));
-                    }
+    /// Siblings must have distinct positive edges, otherwise [`BkError::InvalidEdge`] is
+    /// returned, and every element below an edge must be at the distance of that edge from the
+    /// edge's parent, otherwise [`BkError::CorruptStructure`] is returned.
+    pub fn from_root(dist: D, root: NodeSpec<T>) -> Result<Self, BkError> {
+        fn build<T>(spec: NodeSpec<T>) -> Node<T> {
+            Node::with_children(
+                spec.word,
+                spec.children
+                    .into_iter()
+                    .map(|(arc, child)| (arc, build(child)))
+                    .collect(),
+            )
+        }
+
+        let mut tree = Self::from_parts(Some(build(root)), dist);
+        tree.dirty.mark_all();
 
-                    candidates.extend(
-                        n.children
-                            .iter()
-                            .filter(|(arc, _)| (*arc - distance).abs() <= max_dist)
-                            .map(|(_, node)| node),
-                    );
+        let mut queue: Vec<&Node<T>> = tree.root.iter().collect();
+        while let Some(n) = queue.pop() {
+            for (i, (arc, child)) in n.children.iter().enumerate() {
+                if *arc <= 0 || n.children[..i].iter().any(|(other, _)| other == arc) {
+                    return Err(BkError::InvalidEdge { edge: *arc });
+                }
+                let subtree = Iter { queue: vec![child] };
+                for word in subtree {
+                    let distance = tree.dist.distance(&n.word, word);
+                    if distance < 0 {
+                        return Err(BkError::NonMetric { distance });
+                    }
+                    if distance != *arc {
+                        return Err(BkError::CorruptStructure {
+                            edge: *arc,
+                            distance,
+                        });
+                    }
                 }
-                found
+                queue.push(child);
             }
         }
+        Ok(tree)
     }
-    /// Create an iterator over references of BK-tree elements, in no particular order
-    pub fn iter(&self) -> Iter<T> {
-        let mut queue = Vec::new();
-        if let Some(ref root) = self.root {
-            queue.push(root);
-        }
-        Iter { queue }
+
+    /// Count the elements of the BK-tree
+    ///
+    /// This traverses the whole BK-tree.
+    pub fn len(&self) -> usize {
+        self.iter().count()
     }
-}
 
-impl<T, D> IntoIterator for BkTree<T, D> {
-    type Item = T;
-    type IntoIter = IntoIter<T>;
+    /// Check whether the BK-tree holds no element
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
 
-    fn into_iter(self) -> Self::IntoIter {
-        let mut queue = Vec::new();
-        if let Some(root) = self.root {
-            queue.push(root);
+    /// Insert every element from a given iterator in the BK-tree
+    pub fn insert_all<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for i in iter {
+            self.insert(i);
         }
-        IntoIter { queue }
     }
-}
 
-/// Iterator over BK-tree elements
-pub struct IntoIter<T> {
-    queue: Vec<Node<T>>,
-}
+    /// Insert a new element in the BK-tree, reporting allocation failures instead of aborting
+    ///
+    /// Returns `Ok(false)` if an element at distance 0 was already present, in which case the
+    /// BK-tree is left unchanged. On allocation failure, the BK-tree is also left unchanged and
+    /// the element is dropped.
+    pub fn try_insert(&mut self, val: T) -> Result<bool, TryReserveError> {
+        let canonical = self.check_canonical(&val);
+        if let Canonical::Duplicate = canonical {
+            return Ok(false);
+        }
+        let opts = Insert {
+            fallible: true,
+            ..self.insert_options(None)
+        };
+        let root = match self.root {
+            None => {
+                self.root = Some(Node::new(val));
+                self.dirty.mark_all();
+                self.record_canonical(canonical);
+                return Ok(true);
+            }
+            Some(ref mut root) => root,
+        };
+        let dist = &self.dist;
+        match root.insert_node(val, |a, b| dist.distance(a, b), opts) {
+            Insertion::Added(_, root_edge) => {
+                self.mark_dirty(root_edge);
+                self.record_canonical(canonical);
+                Ok(true)
+            }
+            Insertion::Present(_) => Ok(false),
+            Insertion::Failed(e) => Err(e),
+        }
+    }
 
-impl<T> Iterator for IntoIter<T> {
-    type Item = T;
-    fn next(&mut self) -> Option<Self::Item> {
-        self.queue.pop().map(|node| {
-            self.queue.extend(node.children.into_iter().map(|(_, n)| n));
-            node.word
-        })
+    /// Insert every element from a given iterator in the BK-tree
+    ///
+    /// Returns the number of elements which were not already present
+    pub fn insert_all_counted<I: IntoIterator<Item = T>>(&mut self, iter: I) -> usize {
+        iter.into_iter()
+            .map(|i| self.insert(i))
+            .filter(|new| *new)
+            .count()
     }
-}
 
-/// Iterator over BK-tree elements, by reference
-pub struct Iter<'a, T> {
-    queue: Vec<&'a Node<T>>,
-}
+    /// Merge elements from an external source into the BK-tree, leaving out those at distance 0
+    /// of an element already present
+    ///
+    /// This is [`insert_all_counted`](Self::insert_all_counted): returns the number of elements
+    /// which were newly added
+    pub fn merge_iter<I: IntoIterator<Item = T>>(&mut self, iter: I) -> usize {
+        self.insert_all_counted(iter)
+    }
 
-impl<'a, T> Iterator for Iter<'a, T> {
-    type Item = &'a T;
-    fn next(&mut self) -> Option<Self::Item> {
-        self.queue.pop().map(|node| {
-            self.queue.extend(node.children.iter().map(|(_, n)| n));
-            &node.word
-        })
+    /// Insert every element from a given iterator in the BK-tree
+    ///
+    /// Returns the elements which were dropped because an element at distance 0 was already
+    /// present, which reveals distinct values a metric fails to tell apart
+    pub fn insert_all_reporting_collisions<I>(&mut self, iter: I) -> Vec<T>
+    where
+        I: IntoIterator<Item = T>,
+        T: Clone,
+    {
+        let mut collisions = Vec::new();
+        for i in iter {
+            if !self.insert(i.clone()) {
+                collisions.push(i);
+            }
+        }
+        collisions
     }
-}
 
-#[cfg(test)]
-mod tests {
-    extern crate bincode;
+    /// Insert a new element in the BK-tree
+    ///
+    /// Returns `false` if an element at distance 0 was already present, in which case the BK-tree
+    /// is left unchanged unless it is a [multiset](Self::with_multiset). The root is compared
+    /// first, so inserting an element at distance 0 of the root only takes a single distance
+    /// computation.
+    pub fn insert(&mut self, val: T) -> bool {
+        self.insert_hinted(val, None)
+    }
 
-    use crate::distance::*;
-    use crate::BkTree;
-    #[test]
-    fn levenshtein_distance_test() {
-        let mut bk = BkTree::new(LevenshteinDistance);
-        bk.insert_all(vec![
-            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
-        ]);
-        let (words, dists): (Vec<&str>, Vec<isize>) = bk.find("bo", 2).into_iter().unzip();
-        assert_eq!(words, ["book", "boo", "boon"]);
-        assert_eq!(dists, [2, 1, 2]);
+    /// Insert a new element in the BK-tree, given its distance to the root element
+    ///
+    /// This skips computing the first distance, e.g. when it was already computed in a batch
+    /// along with the distances of other values. The hint is only checked by a debug assertion,
+    /// a wrong hint in release builds inserts the element at a wrong place. It is ignored if the
+    /// BK-tree is empty.
+    pub fn insert_with_root_distance(&mut self, val: T, root_distance: isize) -> bool {
+        self.insert_hinted(val, Some(root_distance))
     }
 
-    #[test]
-    fn hamming_distance_test() {
-        let mut bk = BkTree::new(HammingDistance);
-        bk.insert_all(vec![0, 4, 5, 14, 15]);
+    fn insert_hinted(&mut self, val: T, hint: Option<isize>) -> bool {
+        let canonical = self.check_canonical(&val);
+        if let Canonical::Duplicate = canonical {
+            return false;
+        }
+        let opts = self.insert_options(hint);
+        let root = match self.root {
+            None => {
+                self.root = Some(Node::new(val));
+                self.dirty.mark_all();
+                self.record_canonical(canonical);
+                return true;
+            }
+            Some(ref mut root) => root,
+        };
+        let dist = &self.dist;
+        match root.insert_node(val, |a, b| dist.distance(a, b), opts) {
+            Insertion::Added(_, root_edge) => {
+                self.mark_dirty(root_edge);
+                self.record_canonical(canonical);
+                true
+            }
+            Insertion::Present(_) => false,
+            Insertion::Failed(_) => unreachable!("infallible insertions do not fail"),
+        }
+    }
 
-        let (words, dists): (Vec<i32>, Vec<isize>) = bk.find(13, 1).into_iter().unzip();
-        assert_eq!(words, [5, 15]);
-        assert_eq!(dists, [1, 1]);
+    /// Get the settings of infallible insertions in the BK-tree
+    fn insert_options(&self, hint: Option<isize>) -> Insert {
+        Insert {
+            multiset: self.multiset,
+            threshold: self.index_threshold,
+            hint,
+            fallible: false,
+        }
     }
 
-    #[test]
-    fn iterators_test() {
-        let mut bk = BkTree::new(HammingDistance);
-        bk.insert_all(vec![0, 4, 5, 14, 15]);
+    /// Mark the subtree below a given root edge as changed, or the whole BK-tree for a root
+    /// edge of 0
+    fn mark_dirty(&mut self, root_edge: isize) {
+        if root_edge == 0 {
+            self.dirty.mark_all();
+        } else {
+            self.dirty.mark(root_edge);
+        }
+    }
 
-        let iter_res: Vec<&i32> = bk.iter().collect();
-        assert_eq!(iter_res, [&0, &15, &14, &5, &4]);
-        let intoiter_res: Vec<i32> = bk.into_iter().collect();
-        assert_eq!(intoiter_res, [0, 15, 14, 5, 4]);
+    /// Insert a new element in the BK-tree unless an element at distance 0 is already present
+    ///
+    /// Returns a reference to the stored element, either the existing one or the newly inserted
+    /// one
+    pub fn get_or_insert(&mut self, val: T) -> &T {
+        match self.insert_if_absent(val) {
+            Ok(word) | Err(word) => word,
+        }
+    }
+
+    /// Insert a new element in the BK-tree unless an element at distance 0 is already present
+    ///
+    /// Returns `Ok` with a reference to the newly inserted element, or `Err` with a reference to
+    /// the existing one, in which case `val` is dropped. Even a
+    /// [multiset](Self::with_multiset) BK-tree does not insert `val` in the latter case.
+    ///
+    /// With [canonical deduplication](Self::with_canonical_dedup), the existing element may
+    /// also be one with the same canonical form, which is found by a full traversal.
+    pub fn insert_if_absent(&mut self, val: T) -> Result<&T, &T> {
+        let canonical = self.check_canonical(&val);
+        if let (Canonical::Duplicate, Some(dedup)) = (&canonical, &self.canonical_dedup) {
+            let existing = self.iter().find(|word| dedup.same_form(word, &val));
+            return Err(existing.expect("canonical form recorded without its element"));
+        }
+        let opts = Insert {
+            multiset: false,
+            ..self.insert_options(None)
+        };
+        let root = match self.root {
+            None => {
+                self.dirty.mark_all();
+                self.record_canonical(canonical);
+                return Ok(&self.root.insert(Node::new(val)).word);
+            }
+            Some(ref mut root) => root,
+        };
+        let dist = &self.dist;
+        match root.insert_node(val, |a, b| dist.distance(a, b), opts) {
+            Insertion::Added(node, root_edge) => {
+                self.dirty.mark(root_edge);
+                // Recorded through the field, as `node` still borrows the root
+                if let (Canonical::New(key), Some(dedup)) = (canonical, &mut self.canonical_dedup) {
+                    dedup.record(key);
+                }
+                Ok(&node.word)
+            }
+            Insertion::Present(existing) => Err(&existing.word),
+            Insertion::Failed(_) => unreachable!("infallible insertions do not fail"),
+        }
+    }
+
+    /// Remove every element within `max_dist` of a given value from the BK-tree
+    ///
+    /// The remaining descendants of removed nodes are inserted again. Returns the number of
+    /// removed elements
+    pub fn remove_within(&mut self, val: &T, max_dist: isize) -> usize {
+        let mut removed = 0;
+        let mut orphans = Vec::new();
+        if let Some(root) = self.root.take() {
+            let root_distance = self.dist.distance(&root.word, val);
+            let visited: Vec<isize> = root
+                .children
+                .iter()
+                .map(|(arc, _)| *arc)
+                .filter(|arc| (arc - root_distance).abs() <= max_dist)
+                .collect();
+            self.root = self.remove_within_node(root, val, max_dist, &mut removed, &mut orphans);
+            self.reset_canonical_keys();
+            if root_distance <= max_dist {
+                self.dirty.mark_all();
+            } else if removed > 0 {
+                for arc in visited {
+                    self.dirty.mark(arc);
+                }
+            }
+        }
+        self.insert_all(orphans);
+        removed
+    }
+
+    /// Remove the leaves of the BK-tree matching a given predicate
+    ///
+    /// Leaves can be removed without reinserting anything, which makes this a cheap cleanup
+    /// compared to [`remove_within`](Self::remove_within). Nodes which have children are kept
+    /// even if they match, including the ones whose children all get removed. Returns the
+    /// number of removed elements
+    pub fn prune_leaves<F: FnMut(&T) -> bool>(&mut self, mut pred: F) -> usize {
+        let root = match self.root {
+            Some(ref mut root) => root,
+            None => return 0,
+        };
+        if root.children.is_empty() {
+            if !pred(&root.word) {
+                return 0;
+            }
+            self.root = None;
+            self.dirty.mark_all();
+            self.reset_canonical_keys();
+            return 1;
+        }
+
+        let mut removed = 0;
+        let mut dirty_edges = Vec::new();
+        let mut candidates: Vec<(Option<isize>, &mut Node<T>)> = vec![(None, root)];
+        while let Some((root_edge, n)) = candidates.pop() {
+            let before = n.children.len();
+            n.children
+                .retain(|(_, child)| !(child.children.is_empty() && pred(&child.word)));
+            if n.children.len() < before {
+                removed += before - n.children.len();
+                n.reindex(self.index_threshold);
+                // Removing children of the root changes the whole BK-tree
+                dirty_edges.push(root_edge.unwrap_or(0));
+            }
+            candidates.extend(
+                n.children
+                    .iter_mut()
+                    .map(|(arc, child)| (Some(root_edge.unwrap_or(*arc)), child)),
+            );
+        }
+        for edge in dirty_edges {
+            self.mark_dirty(edge);
+        }
+        if removed > 0 {
+            self.reset_canonical_keys();
+        }
+        removed
+    }
+
+    /// Keep only the `k` elements of the BK-tree closest to `centroid`, dropping the others
+    ///
+    /// Ties are broken in favor of elements closer to the root. This computes the distance from
+    /// every element to `centroid` and reinserts the kept elements, so it always costs a full
+    /// traversal followed by `k` insertions.
+    pub fn keep_nearest(&mut self, centroid: &T, k: usize) {
+        // Elements come in breadth-first order, which the stable sort keeps among ties
+        let mut words: Vec<(isize, T)> = IntoBfs {
+            queue: self
+                .root
+                .take()
+                .into_iter()
+                .map(|root| (None, root))
+                .collect(),
+        }
+        .map(|(word, _)| (self.dist.distance(&word, centroid), word))
+        .collect();
+        words.sort_by_key(|(dist, _)| *dist);
+        self.reset_canonical_keys();
+        self.insert_all(words.into_iter().take(k).map(|(_, word)| word));
+        self.dirty.mark_all();
+    }
+
+    /// Keep only the elements of the BK-tree within `radius` of `center`, dropping the others
+    ///
+    /// The kept elements are found by a search, cloned and inserted in a new BK-tree, so this
+    /// is cheaper than [`keep_nearest`](Self::keep_nearest) when few elements are kept. Returns
+    /// the number of dropped elements
+    pub fn trim_to_radius(&mut self, center: &T, radius: isize) -> usize
+    where
+        T: Clone,
+    {
+        let len = self.len();
+        let kept: Vec<T> = self
+            .find_by(|word| self.dist.distance(word, center), radius)
+            .into_iter()
+            .map(|(word, _)| word.clone())
+            .collect();
+        let dropped = len - kept.len();
+        self.root = None;
+        self.reset_canonical_keys();
+        self.insert_all(kept);
+        self.dirty.mark_all();
+        dropped
+    }
+
+    fn remove_within_node(
+        &self,
+        mut node: Node<T>,
+        val: &T,
+        max_dist: isize,
+        removed: &mut usize,
+        orphans: &mut Vec<T>,
+    ) -> Option<Node<T>> {
+        let distance = self.dist.distance(&node.word, val);
+        if distance <= max_dist {
+            *removed += 1;
+            let descendants = IntoIter {
+                queue: node.children.into_iter().map(|(_, child)| child).collect(),
+            };
+            for word in descendants {
+                if self.dist.distance(&word, val) <= max_dist {
+                    *removed += 1;
+                } else {
+                    orphans.push(word);
+                }
+            }
+            return None;
+        }
+
+        let mut children = Vec::with_capacity(node.children.len());
+        for (arc, child) in node.children {
+            if (arc - distance).abs() > max_dist {
+                children.push((arc, child));
+            } else if let Some(child) =
+                self.remove_within_node(child, val, max_dist, removed, orphans)
+            {
+                children.push((arc, child));
+            }
+        }
+        node.children = children;
+        node.reindex(self.index_threshold);
+        Some(node)
+    }
+
+    /// Move every element satisfying `pred` into a new BK-tree, keeping the others
+    ///
+    /// Both BK-trees are rebuilt from their elements. The new BK-tree has the same settings as
+    /// this one, e.g. it keeps elements at distance 0 of each other if this one is a multiset.
+    pub fn split_off<F: FnMut(&T) -> bool>(&mut self, pred: F) -> Self
+    where
+        D: Clone,
+    {
+        let words = IntoIter {
+            queue: self.root.take().into_iter().collect(),
+        };
+        let (split, kept): (Vec<T>, Vec<T>) = words.partition(pred);
+        self.dirty.mark_all();
+        self.reset_canonical_keys();
+
+        self.insert_all(kept);
+        let mut other = Self::new(self.dist.clone());
+        other.multiset = self.multiset;
+        other.index_threshold = self.index_threshold;
+        other.canonical_dedup = self.canonical_dedup.as_ref().map(CanonicalDedup::emptied);
+        other.insert_all(split);
+        other
+    }
+
+    /// Consume the BK-tree into a new BK-tree holding the result of `f` on each element, under
+    /// another distance function
+    ///
+    /// The new BK-tree is built by insertion, so its structure follows the new distance function
+    /// and elements mapped to values at distance 0 are only kept once.
+    pub fn map_into<T2, D2, F>(self, f: F, dist2: D2) -> BkTree<T2, D2>
+    where
+        F: Fn(T) -> T2,
+        D2: Distance<T2>,
+    {
+        let mut mapped = BkTree::new(dist2);
+        mapped.insert_all(self.into_iter().map(f));
+        mapped
+    }
+
+    /// Find the closest elements to a given value present in the BK-tree
+    ///
+    /// Returns pairs of element references and distances
+    pub fn find(&self, val: T, max_dist: isize) -> Vec<(&T, isize)> {
+        self.find_borrowed(&val, max_dist)
+    }
+
+    /// Find the closest elements to a given value present in the BK-tree, grouped by distance
+    ///
+    /// Within each group, elements are in the order [`find`](Self::find) would return them.
+    pub fn find_grouped(&self, val: T, max_dist: isize) -> BTreeMap<isize, Vec<&T>> {
+        let mut groups: BTreeMap<isize, Vec<&T>> = BTreeMap::new();
+        for (word, distance) in self.find(val, max_dist) {
+            groups.entry(distance).or_default().push(word);
+        }
+        groups
+    }
+
+    /// Find the closest elements to a given value present in the BK-tree for which `keep`
+    /// returns `true`
+    ///
+    /// Elements left out by `keep` are still used to prune the search, `keep` is only called on
+    /// the elements within `max_dist`.
+    pub fn find_filtered<F>(&self, val: T, max_dist: isize, keep: F) -> Vec<(&T, isize)>
+    where
+        F: Fn(&T) -> bool,
+    {
+        let mut found = Vec::new();
+        let distance_to = |word: &T| self.dist.distance(word, &val);
+        self.for_each_by_pruning(distance_to, max_dist, max_dist, |word, distance| {
+            if keep(word) {
+                found.push((word, distance));
+            }
+        });
+        found
+    }
+
+    /// Find the closest elements to a given value present in the BK-tree, as borrowed
+    /// [`Cow`]s
+    ///
+    /// Results can be turned into owned values with [`Cow::into_owned`] only when they need to
+    /// outlive the BK-tree.
+    pub fn find_cow(&self, val: T, max_dist: isize) -> Vec<(Cow<'_, T>, isize)>
+    where
+        T: Clone,
+    {
+        self.find(val, max_dist)
+            .into_iter()
+            .map(|(word, distance)| (Cow::Borrowed(word), distance))
+            .collect()
+    }
+
+    /// Call `f` on each element within `max_dist` of a given value, with its distance, in the
+    /// order [`find`](Self::find) would return them
+    ///
+    /// Matches are passed to `f` as soon as they are found, without collecting them.
+    pub fn for_each_match<F: FnMut(&T, isize)>(&self, val: T, max_dist: isize, f: F) {
+        self.for_each_by_pruning(|word| self.dist.distance(word, &val), max_dist, max_dist, f)
+    }
+
+    /// Find the closest elements to a given value present in the BK-tree, collecting at most
+    /// `max_bytes` of results
+    ///
+    /// The size of the results is approximated as their count times the size of a result pair.
+    /// The search stops at the first match which would not fit, in which case the returned flag
+    /// is `true` and the results are the ones [`find`](Self::find) would return first.
+    pub fn find_memory_bounded(
+        &self,
+        val: T,
+        max_dist: isize,
+        max_bytes: usize,
+    ) -> (Vec<(&T, isize)>, bool) {
+        let capacity = max_bytes / std::mem::size_of::<(&T, isize)>();
+        let mut found = Vec::new();
+        let mut candidates: std::collections::VecDeque<&Node<T>> = self.root.iter().collect();
+
+        while let Some(n) = candidates.pop_front() {
+            let distance = self.dist.distance(&n.word, &val);
+            if distance <= max_dist {
+                if found.len() == capacity {
+                    return (found, true);
+                }
+                found.push((&n.word, distance));
+            }
+
+            candidates.extend(
+                n.children
+                    .iter()
+                    .filter(|(arc, _)| (*arc - distance).abs() <= max_dist)
+                    .map(|(_, node)| node),
+            );
+        }
+        (found, false)
+    }
+
+    /// Find the closest element to a given value present in the BK-tree
+    ///
+    /// Returns the element reference and its distance, or `None` if the BK-tree is empty
+    pub fn find_nearest(&self, val: T) -> Option<(&T, isize)> {
+        let mut best: Option<(&T, isize)> = None;
+        let mut candidates: Vec<&Node<T>> = self.root.iter().collect();
+        while let Some(n) = candidates.pop() {
+            let distance = self.dist.distance(&n.word, &val);
+            if best.is_none_or(|(_, best_dist)| distance < best_dist) {
+                best = Some((&n.word, distance));
+                if distance == 0 {
+                    break;
+                }
+            }
+
+            let bound = best.map_or(isize::MAX, |(_, best_dist)| best_dist);
+            candidates.extend(
+                n.children
+                    .iter()
+                    .filter(|(arc, _)| (*arc - distance).abs() < bound)
+                    .map(|(_, node)| node),
+            );
+        }
+        best
+    }
+
+    /// Find an element within `good_enough` of a given value present in the BK-tree, or the
+    /// closest element if there is none
+    ///
+    /// Subtrees are explored in increasing order of the lowest distance they may hold, and the
+    /// search stops at the first element within `good_enough`, which may not be the closest one.
+    /// Returns the element reference and its distance, or `None` if the BK-tree is empty
+    pub fn find_nearest_until(&self, val: T, good_enough: isize) -> Option<(&T, isize)> {
+        let mut best: Option<(&T, isize)> = None;
+        let mut nodes: Vec<&Node<T>> = self.root.iter().collect();
+        let mut frontier: BinaryHeap<(Reverse<isize>, usize)> =
+            (0..nodes.len()).map(|i| (Reverse(0), i)).collect();
+        while let Some((Reverse(lower_bound), i)) = frontier.pop() {
+            if best.is_some_and(|(_, best_dist)| lower_bound >= best_dist) {
+                break;
+            }
+            let n = nodes[i];
+            let distance = self.dist.distance(&n.word, &val);
+            if best.is_none_or(|(_, best_dist)| distance < best_dist) {
+                best = Some((&n.word, distance));
+                if distance <= good_enough {
+                    break;
+                }
+            }
+
+            for (arc, child) in &n.children {
+                let bound = lower_bound.max((*arc - distance).abs());
+                if best.is_none_or(|(_, best_dist)| bound < best_dist) {
+                    nodes.push(child);
+                    frontier.push((Reverse(bound), nodes.len() - 1));
+                }
+            }
+        }
+        best
+    }
+
+    /// Find the farthest element within `max_dist` of a given value present in the BK-tree
+    ///
+    /// Returns the element reference and its distance, or `None` if no element is within
+    /// `max_dist`
+    pub fn find_farthest_within(&self, val: T, max_dist: isize) -> Option<(&T, isize)> {
+        let mut farthest: Option<(&T, isize)> = None;
+        let mut candidates: Vec<&Node<T>> = self.root.iter().collect();
+        while let Some(n) = candidates.pop() {
+            let distance = self.dist.distance(&n.word, &val);
+            if distance <= max_dist && farthest.is_none_or(|(_, far)| distance > far) {
+                farthest = Some((&n.word, distance));
+            }
+
+            candidates.extend(
+                n.children
+                    .iter()
+                    .filter(|(arc, _)| (*arc - distance).abs() <= max_dist)
+                    .map(|(_, node)| node),
+            );
+        }
+        farthest
+    }
+
+    /// Find the closest elements to a given value present in the BK-tree, sorted by distance and
+    /// then by element
+    ///
+    /// Unlike [`find`](Self::find), whose order follows the shape of the tree and therefore the
+    /// insertion history, the order of the results only depends on the set of elements and the
+    /// query.
+    pub fn find_sorted(&self, val: T, max_dist: isize) -> Vec<(&T, isize)>
+    where
+        T: Ord,
+    {
+        self.find_sorted_by(val, max_dist, T::cmp)
+    }
+
+    /// Find the closest elements to a given value present in the BK-tree, sorted by distance and
+    /// then by `tie_break` among elements at the same distance
+    pub fn find_sorted_by<F>(&self, val: T, max_dist: isize, tie_break: F) -> Vec<(&T, isize)>
+    where
+        F: Fn(&T, &T) -> Ordering,
+    {
+        let mut found = self.find_borrowed(&val, max_dist);
+        found.sort_by(|(a, a_dist), (b, b_dist)| a_dist.cmp(b_dist).then_with(|| tie_break(a, b)));
+        found
+    }
+
+    /// Write the closest elements to a given value present in the BK-tree as CSV
+    ///
+    /// The output starts with a `word,distance` header followed by one row per match, sorted by
+    /// distance. Words containing commas, quotes or line breaks are quoted.
+    #[cfg(feature = "std")]
+    pub fn write_find_csv<W: Write>(&self, val: T, max_dist: isize, mut out: W) -> io::Result<()>
+    where
+        T: Display,
+    {
+        let mut found = self.find_borrowed(&val, max_dist);
+        found.sort_by_key(|&(_, dist)| dist);
+        writeln!(out, "word,distance")?;
+        for (word, dist) in found {
+            let word = word.to_string();
+            if word.contains([',', '"', '\n', '\r']) {
+                writeln!(out, "\"{}\",{}", word.replace('"', "\"\""), dist)?;
+            } else {
+                writeln!(out, "{},{}", word, dist)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the closest elements to any of the given values present in the BK-tree
+    ///
+    /// Each element appears once, with its smallest distance to the given values.
+    pub fn find_any(&self, vals: &[T], max_dist: isize) -> Vec<(&T, isize)> {
+        let mut found: Vec<(&T, isize)> = Vec::new();
+        let mut positions: HashMap<*const T, usize> = HashMap::new();
+        for val in vals {
+            for (word, dist) in self.find_borrowed(val, max_dist) {
+                match positions.get(&(word as *const T)) {
+                    Some(&pos) => found[pos].1 = found[pos].1.min(dist),
+                    None => {
+                        positions.insert(word, found.len());
+                        found.push((word, dist));
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    /// Find the closest elements to a given value present in the BK-tree, keeping only the
+    /// closest element for each distinct key returned by `key_fn`
+    pub fn find_dedup_by<K, F>(&self, val: T, max_dist: isize, key_fn: F) -> Vec<(&T, isize)>
+    where
+        K: Eq + Hash,
+        F: Fn(&T) -> K,
+    {
+        let mut found: Vec<(&T, isize)> = Vec::new();
+        let mut positions: HashMap<K, usize> = HashMap::new();
+        for (word, dist) in self.find_borrowed(&val, max_dist) {
+            match positions.entry(key_fn(word)) {
+                Entry::Occupied(e) => {
+                    let pos = *e.get();
+                    if dist < found[pos].1 {
+                        found[pos] = (word, dist);
+                    }
+                }
+                Entry::Vacant(e) => {
+                    e.insert(found.len());
+                    found.push((word, dist));
+                }
+            }
+        }
+        found
+    }
+
+    /// Get every element of the BK-tree with its distance to a given value, sorted by ascending
+    /// distance
+    ///
+    /// This visits every element without any pruning, which makes it suitable for ranking the
+    /// whole BK-tree where [`find`](Self::find) with `isize::MAX` could overflow.
+    pub fn find_all_by_distance(&self, val: T) -> Vec<(&T, isize)> {
+        let mut found: Vec<(&T, isize)> = self
+            .iter()
+            .map(|word| (word, self.dist.distance(word, &val)))
+            .collect();
+        found.sort_by_key(|(_, dist)| *dist);
+        found
+    }
+
+    /// Create an iterator over the elements of the BK-tree in increasing distance to `pivot`,
+    /// with their distances
+    ///
+    /// Unlike [`find_all_by_distance`](Self::find_all_by_distance), elements are found lazily
+    /// by a best-first traversal: subtrees are only explored once the lowest distance they may
+    /// hold is reached, so stopping early, e.g. with `take_while`, saves distance computations.
+    pub fn iter_by_distance<'a>(&'a self, pivot: &'a T) -> IterByDistance<'a, T, D> {
+        let mut iter = IterByDistance {
+            dist: &self.dist,
+            pivot,
+            nodes: Vec::new(),
+            frontier: BinaryHeap::new(),
+        };
+        if let Some(ref root) = self.root {
+            iter.push_node(root, 0);
+        }
+        iter
+    }
+
+    /// Find the closest elements to a given value present in the BK-tree, except the value itself
+    ///
+    /// Only elements equal to the value are skipped: distinct elements at distance 0 are still
+    /// returned.
+    pub fn find_excluding_self(&self, val: T, max_dist: isize) -> Vec<(&T, isize)>
+    where
+        T: PartialEq,
+    {
+        let mut found = self.find_borrowed(&val, max_dist);
+        found.retain(|(word, dist)| *dist != 0 || **word != val);
+        found
+    }
+
+    /// Consume the BK-tree into its elements along with their distance to a pivot, sorted by
+    /// ascending distance
+    pub fn into_sorted_by_distance(self, pivot: &T) -> Vec<(T, isize)> {
+        let dist = self.dist;
+        let words = IntoIter {
+            queue: self.root.into_iter().collect(),
+        };
+        let mut sorted: Vec<(T, isize)> = words
+            .map(|word| {
+                let d = dist.distance(&word, pivot);
+                (word, d)
+            })
+            .collect();
+        sorted.sort_by_key(|(_, d)| *d);
+        sorted
+    }
+
+    /// Find the closest elements to a given value, expanding the search radius from 0 until at
+    /// least `min_results` elements are found or `max_radius` is reached
+    ///
+    /// Each expansion re-traverses the tree from the root, so for large radii a single search
+    /// with an upper bound is preferable.
+    pub fn find_expanding(
+        &self,
+        val: T,
+        min_results: usize,
+        max_radius: isize,
+    ) -> Vec<(&T, isize)> {
+        let mut found = Vec::new();
+        for radius in 0..=max_radius {
+            found = self.find_borrowed(&val, radius);
+            if found.len() >= min_results {
+                break;
+            }
+        }
+        found
+    }
+
+    /// Find the closest elements to a given value present in the BK-tree, preparing the query
+    /// once with [`PreparedDistance::prepare`] instead of on every distance computation
+    ///
+    /// Returns the same results as [`find`](Self::find)
+    pub fn find_prepared(&self, val: T, max_dist: isize) -> Vec<(&T, isize)>
+    where
+        D: PreparedDistance<T>,
+    {
+        let prepared = self.dist.prepare(&val);
+        self.find_by(
+            |word| self.dist.distance_prepared(&prepared, word),
+            max_dist,
+        )
+    }
+
+    /// Find the closest elements to a given value present in the BK-tree, pruning more
+    /// aggressively than [`find`](Self::find)
+    ///
+    /// Children are only visited if their edge is within `max_dist - slack` of the distance to
+    /// their parent, instead of `max_dist`. This visits fewer nodes but may miss some elements
+    /// within `max_dist`: larger slacks are faster with a lower recall. A slack of 0 is exact.
+    pub fn find_approx(&self, val: T, max_dist: isize, slack: isize) -> Vec<(&T, isize)> {
+        self.find_by_pruning(
+            |word| self.dist.distance(word, &val),
+            max_dist,
+            max_dist - slack,
+        )
+    }
+
+    /// Find the closest elements to a given value present in the BK-tree, along with their
+    /// similarity ratio to it
+    ///
+    /// The ratio is `1.0 - distance / max_distance`, where `max_distance` is given by
+    /// [`NormalizedDistance::max_distance`], e.g. the length of the longest string for
+    /// Levenshtein distance. It is 1.0 for elements identical to the value.
+    pub fn find_with_ratio(&self, val: T, max_dist: isize) -> Vec<(&T, f64)>
+    where
+        D: NormalizedDistance<T>,
+    {
+        self.find_borrowed(&val, max_dist)
+            .into_iter()
+            .map(|(word, dist)| {
+                let max = self.dist.max_distance(word, &val);
+                let ratio = if max == 0 {
+                    1.0
+                } else {
+                    1.0 - dist as f64 / max as f64
+                };
+                (word, ratio)
+            })
+            .collect()
+    }
+
+    /// Find the closest elements to a borrowed form of a value present in the BK-tree
+    ///
+    /// This allows querying trees of owned values without building an owned query, e.g. a
+    /// `BkTree<Box<str>>` can be searched with a `&str`.
+    ///
+    /// ```rust
+    /// use bktree::*;
+    ///
+    /// let mut bk: BkTree<Box<str>> = BkTree::new(LevenshteinDistance);
+    /// bk.insert_all(vec!["book".into(), "boo".into(), "cake".into()]);
+    ///
+    /// let words: Vec<&str> = bk.find_borrowed("bo", 1).into_iter().map(|(w, _)| &**w).collect();
+    /// assert_eq!(words, ["boo"]);
+    /// ```
+    pub fn find_borrowed<Q>(&self, val: &Q, max_dist: isize) -> Vec<(&T, isize)>
+    where
+        Q: ?Sized,
+        T: Borrow<Q>,
+        D: Distance<Q>,
+    {
+        if Distance::<Q>::bounded_max(&self.dist).is_some_and(|bound| max_dist >= bound) {
+            // Every element matches, visit them all in the same order without pruning
+            let mut found = Vec::new();
+            let mut candidates: std::collections::VecDeque<&Node<T>> = self.root.iter().collect();
+            while let Some(n) = candidates.pop_front() {
+                found.push((&n.word, self.dist.distance(n.word.borrow(), val)));
+                candidates.extend(n.children.iter().map(|(_, node)| node));
+            }
+            return found;
+        }
+        self.find_by(|word| self.dist.distance(word.borrow(), val), max_dist)
+    }
+
+    /// Find the closest elements to a query given as a stream of characters
+    ///
+    /// The query is never collected, so this suits streaming metrics on large queries.
+    ///
+    /// ```rust
+    /// use bktree::*;
+    ///
+    /// let mut bk = BkTree::new(Materialized(LevenshteinDistance));
+    /// bk.insert_all(vec!["book", "boo", "cake"]);
+    ///
+    /// let query = "bo".chars().chain("o".chars());
+    /// assert_eq!(bk.find_stream(query, 0), [(&"boo", 0)]);
+    /// ```
+    pub fn find_stream<I>(&self, query: I, max_dist: isize) -> Vec<(&T, isize)>
+    where
+        I: Iterator<Item = char> + Clone,
+        T: AsRef<str>,
+        D: StreamingDistance<char>,
+    {
+        self.find_by(
+            |word| {
+                self.dist
+                    .distance_streams(word.as_ref().chars(), query.clone())
+            },
+            max_dist,
+        )
+    }
+
+    /// Find the closest elements to a given slice, for BK-trees whose elements can be borrowed
+    /// as slices
+    ///
+    /// This is [`find_borrowed`](Self::find_borrowed) for slices, letting arrays and byte
+    /// string literals coerce to the query type.
+    ///
+    /// ```rust
+    /// use bktree::*;
+    ///
+    /// let mut bk: BkTree<Vec<u8>, _> = BkTree::new(ByteLevenshteinDistance);
+    /// bk.insert_all(vec![b"book".to_vec(), b"boo".to_vec(), b"cake".to_vec()]);
+    ///
+    /// assert_eq!(bk.find_slice(b"bo", 1), [(&b"boo".to_vec(), 1)]);
+    /// ```
+    pub fn find_slice<E>(&self, query: &[E], max_dist: isize) -> Vec<(&T, isize)>
+    where
+        T: Borrow<[E]>,
+        D: Distance<[E]>,
+    {
+        self.find_borrowed(query, max_dist)
+    }
+
+    /// Breadth-first search using `distance_to` as the distance from each element to the query
+    pub(crate) fn find_by<F>(&self, distance_to: F, max_dist: isize) -> Vec<(&T, isize)>
+    where
+        F: Fn(&T) -> isize,
+    {
+        self.find_by_pruning(distance_to, max_dist, max_dist)
+    }
+
+    /// Breadth-first search only expanding children whose edge is within `prune_dist` of the
+    /// distance to their parent
+    fn find_by_pruning<F>(
+        &self,
+        distance_to: F,
+        max_dist: isize,
+        prune_dist: isize,
+    ) -> Vec<(&T, isize)>
+    where
+        F: Fn(&T) -> isize,
+    {
+        let mut found = Vec::new();
+        self.for_each_by_pruning(distance_to, max_dist, prune_dist, |word, distance| {
+            found.push((word, distance))
+        });
+        found
+    }
+
+    /// Breadth-first search using `distance_to` as the distance from each element to the query,
+    /// given the distance function, returning mutable references to the matches
+    ///
+    /// Callers must not change the matches in a way which changes their distances to other
+    /// elements.
+    pub(crate) fn find_by_mut<F>(&mut self, distance_to: F, max_dist: isize) -> Vec<(&mut T, isize)>
+    where
+        F: Fn(&D, &T) -> isize,
+    {
+        let mut found = Vec::new();
+        let mut candidates: std::collections::VecDeque<&mut Node<T>> =
+            self.root.iter_mut().collect();
+
+        while let Some(Node { word, children, .. }) = candidates.pop_front() {
+            let distance = distance_to(&self.dist, word);
+            candidates.extend(
+                children
+                    .iter_mut()
+                    .filter(|(arc, _)| (*arc - distance).abs() <= max_dist)
+                    .map(|(_, node)| node),
+            );
+            if distance <= max_dist {
+                found.push((word, distance));
+            }
+        }
+        if !found.is_empty() {
+            self.dirty.mark_all();
+        }
+        found
+    }
+
+    /// Breadth-first search calling `f` on each match, only expanding children whose edge is
+    /// within `prune_dist` of the distance to their parent
+    fn for_each_by_pruning<'a, F, G>(
+        &'a self,
+        distance_to: F,
+        max_dist: isize,
+        prune_dist: isize,
+        mut f: G,
+    ) where
+        F: Fn(&T) -> isize,
+        G: FnMut(&'a T, isize),
+    {
+        let mut candidates: std::collections::VecDeque<&Node<T>> = self.root.iter().collect();
+
+        while let Some(n) = candidates.pop_front() {
+            let distance = distance_to(&n.word);
+            if distance <= max_dist {
+                f(&n.word, distance);
+            }
+
+            candidates.extend(
+                n.children
+                    .iter()
+                    .filter(|(arc, _)| (*arc - distance).abs() <= prune_dist)
+                    .map(|(_, node)| node),
+            );
+        }
+    }
+
+    /// Check whether any element of the BK-tree is within `max_dist` of a given value
+    ///
+    /// The traversal stops at the first match found.
+    pub fn contains_within(&self, val: &T, max_dist: isize) -> bool {
+        let mut candidates: Vec<&Node<T>> = self.root.iter().collect();
+        while let Some(n) = candidates.pop() {
+            let distance = self.dist.distance(&n.word, val);
+            if distance <= max_dist {
+                return true;
+            }
+
+            candidates.extend(
+                n.children
+                    .iter()
+                    .filter(|(arc, _)| (*arc - distance).abs() <= max_dist)
+                    .map(|(_, node)| node),
+            );
+        }
+        false
+    }
+
+    /// Check for each given value whether any element of the BK-tree is within `max_dist` of it
+    ///
+    /// The results are in the order of the values, as by
+    /// [`contains_within`](Self::contains_within) on each of them.
+    pub fn contains_within_batch(&self, vals: &[T], max_dist: isize) -> Vec<bool> {
+        vals.iter()
+            .map(|val| self.contains_within(val, max_dist))
+            .collect()
+    }
+
+    /// Check whether every given value has an element at distance 0 in the BK-tree
+    pub fn contains_all<'a, I>(&self, vals: I) -> bool
+    where
+        I: IntoIterator<Item = &'a T>,
+        T: 'a,
+    {
+        vals.into_iter().all(|val| self.contains_within(val, 0))
+    }
+
+    /// Find the given values which have no element at distance 0 in the BK-tree
+    pub fn find_missing<'a, I>(&self, vals: I) -> Vec<&'a T>
+    where
+        I: IntoIterator<Item = &'a T>,
+        T: 'a,
+    {
+        vals.into_iter()
+            .filter(|val| !self.contains_within(val, 0))
+            .collect()
+    }
+
+    /// Check whether a value equal to a given one is stored in the BK-tree
+    ///
+    /// Unlike [`contains_within`](Self::contains_within) at distance 0, this tells apart values
+    /// which are distinct but at distance 0 under a pseudometric.
+    pub fn contains_exact(&self, val: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.nodes_of(val).iter().any(|n| n.word == *val)
+    }
+
+    /// Get the number of children of the node holding the element at distance 0 of a given
+    /// value, or `None` if there is no such element
+    ///
+    /// In a multiset BK-tree, the elements at distance 0 of the value count as a single node,
+    /// holding the children of all of them.
+    pub fn children_of(&self, val: &T) -> Option<usize> {
+        self.children_words_of(val).map(|children| children.len())
+    }
+
+    /// Get the immediate children of the node holding the element at distance 0 of a given
+    /// value, with the distances on their edges, or `None` if there is no such element
+    ///
+    /// In a multiset BK-tree, the elements at distance 0 of the value count as a single node,
+    /// holding the children of all of them.
+    pub fn children_words_of(&self, val: &T) -> Option<Vec<(&T, isize)>> {
+        let nodes = self.nodes_of(val);
+        if nodes.is_empty() {
+            return None;
+        }
+        Some(
+            nodes
+                .iter()
+                .flat_map(|n| n.children.iter())
+                .filter(|(dist, _)| *dist != 0)
+                .map(|(dist, child)| (&child.word, *dist))
+                .collect(),
+        )
+    }
+
+    /// Get the node holding the element at distance 0 of a given value
+    fn node_of(&self, val: &T) -> Option<&Node<T>> {
+        let mut u = self.root.as_ref()?;
+        loop {
+            let k = self.dist.distance(&u.word, val);
+            if k == 0 {
+                return Some(u);
+            }
+            u = &u.children[u.child_position(k)?].1;
+        }
+    }
+
+    /// Get the nodes holding the elements at distance 0 of a given value
+    ///
+    /// In a multiset BK-tree, the elements at distance 0 of the first one found are stored below
+    /// it under edges of distance 0.
+    fn nodes_of(&self, val: &T) -> Vec<&Node<T>> {
+        let mut nodes: Vec<&Node<T>> = self.node_of(val).into_iter().collect();
+        let mut i = 0;
+        while self.multiset && i < nodes.len() {
+            let n = nodes[i];
+            nodes.extend(
+                n.children
+                    .iter()
+                    .filter(|(dist, _)| *dist == 0)
+                    .map(|(_, child)| child),
+            );
+            i += 1;
+        }
+        nodes
+    }
+
+    /// Estimate the largest distance between two elements of the BK-tree
+    ///
+    /// This uses a double sweep: find the farthest element from the root, then return the
+    /// largest distance from that element. It takes two full traversals and returns a lower bound
+    /// of the true diameter, which is 0 for an empty BK-tree.
+    pub fn approx_diameter(&self) -> isize {
+        let farthest_from = |val: &T| {
+            self.iter()
+                .map(|word| (word, self.dist.distance(word, val)))
+                .max_by_key(|(_, dist)| *dist)
+        };
+        self.root
+            .as_ref()
+            .and_then(|root| farthest_from(&root.word))
+            .and_then(|(word, _)| farthest_from(word))
+            .map_or(0, |(_, dist)| dist)
+    }
+
+    /// Get the number of edges on the longest path from the root to a leaf
+    pub fn depth(&self) -> usize {
+        self.root.as_ref().map_or(0, Node::depth)
+    }
+
+    /// Check whether the depth of the BK-tree is at most `round(factor * log2(n + 1))`, `n`
+    /// being its number of elements
+    pub fn is_balanced(&self, factor: f64) -> bool {
+        let bound = (factor * ((self.len() + 1) as f64).log2()).round();
+        self.depth() as f64 <= bound
+    }
+
+    /// Get the number of nodes at each depth of the BK-tree, the root being at depth 0
+    ///
+    /// Returns an empty vector for an empty BK-tree.
+    pub fn level_widths(&self) -> Vec<usize> {
+        let mut widths = Vec::new();
+        let mut level: Vec<&Node<T>> = self.root.iter().collect();
+        while !level.is_empty() {
+            widths.push(level.len());
+            level = level
+                .into_iter()
+                .flat_map(|n| n.children.iter().map(|(_, child)| child))
+                .collect();
+        }
+        widths
+    }
+
+    /// Rebuild the BK-tree if it is deeper than `max_depth`, trying to bring its depth under
+    /// `max_depth`
+    ///
+    /// The BK-tree is rebuilt as by [`rebuild`](Self::rebuild). This greedy strategy is not
+    /// guaranteed to reach `max_depth`, which may not even be reachable: elements which are all
+    /// at the same distance from one another can only form a chain.
+    pub fn truncate_depth(&mut self, max_depth: usize) {
+        if self.depth() > max_depth {
+            self.rebuild();
+        }
+    }
+
+    /// Rebuild the BK-tree to reduce its depth
+    ///
+    /// Each subtree's root is chosen as the element which splits the other elements into the
+    /// smallest largest group of equidistant elements, which takes a time quadratic in the number
+    /// of elements.
+    pub fn rebuild(&mut self) {
+        let words: Vec<T> = IntoIter {
+            queue: self.root.take().into_iter().collect(),
+        }
+        .collect();
+        self.root = self.build_balanced(words);
+        self.dirty.mark_all();
+    }
+
+    /// Predict the depth the BK-tree would have after a [`rebuild`](Self::rebuild), without
+    /// modifying it
+    ///
+    /// This performs the same work as a rebuild on a copy of the elements.
+    pub fn rebuild_preview(&self) -> usize
+    where
+        T: Clone,
+    {
+        self.build_balanced(self.iter().cloned().collect())
+            .map_or(0, |root| root.depth())
+    }
+
+    fn build_balanced(&self, mut words: Vec<T>) -> Option<Node<T>> {
+        let largest_group = |pivot: &T, words: &[T]| {
+            let mut counts: HashMap<isize, usize> = HashMap::new();
+            for word in words {
+                *counts.entry(self.dist.distance(pivot, word)).or_insert(0) += 1;
+            }
+            counts.into_values().max()
+        };
+        let pivot = (0..words.len()).min_by_key(|i| largest_group(&words[*i], &words))?;
+        let word = words.swap_remove(pivot);
+
+        let mut groups: BTreeMap<isize, Vec<T>> = BTreeMap::new();
+        for other in words {
+            let k = self.dist.distance(&word, &other);
+            // Elements at distance 0 are duplicates, unless this is a multiset
+            if k != 0 || self.multiset {
+                groups.entry(k).or_default().push(other);
+            }
+        }
+        let children = groups
+            .into_iter()
+            .filter_map(|(k, group)| self.build_balanced(group).map(|child| (k, child)))
+            .collect();
+        let mut node = Node::with_children(word, children);
+        node.reindex(self.index_threshold);
+        Some(node)
+    }
+
+    /// Find the elements only present in one of two BK-trees
+    ///
+    /// Elements are compared by equality rather than by distance, and their order follows
+    /// [`iter`](Self::iter). Returns `(only_in_self, only_in_other)`
+    pub fn diff<'a>(&'a self, other: &'a Self) -> (Vec<&'a T>, Vec<&'a T>)
+    where
+        T: Eq + Hash,
+    {
+        let ours: HashSet<&T> = self.iter().collect();
+        let theirs: HashSet<&T> = other.iter().collect();
+        (
+            self.iter().filter(|word| !theirs.contains(word)).collect(),
+            other.iter().filter(|word| !ours.contains(word)).collect(),
+        )
+    }
+
+    /// Draw `k` distinct elements of the BK-tree at random, or all of them if there are fewer
+    ///
+    /// This uses reservoir sampling over a single traversal, so only the sample is kept in
+    /// memory. The same seed always yields the same sample from the same BK-tree.
+    pub fn sample(&self, k: usize, seed: u64) -> Vec<&T> {
+        let mut rng = SplitMix64(seed);
+        let mut reservoir = Vec::with_capacity(k);
+        for (i, word) in self.iter().enumerate() {
+            if i < k {
+                reservoir.push(word);
+            } else {
+                let j = rng.below(i as u64 + 1) as usize;
+                if j < k {
+                    reservoir[j] = word;
+                }
+            }
+        }
+        reservoir
+    }
+
+    /// Compute the distance between every pair of elements of the BK-tree
+    ///
+    /// Rows and columns follow the order of [`iter`](Self::iter). This takes a time and memory
+    /// quadratic in the number of elements, so it is only meant for small BK-trees.
+    pub fn pairwise_distances(&self) -> Vec<Vec<isize>> {
+        let words: Vec<&T> = self.iter().collect();
+        let mut matrix = vec![vec![0; words.len()]; words.len()];
+        for (i, a) in words.iter().enumerate() {
+            for (j, b) in words.iter().enumerate().skip(i + 1) {
+                let dist = self.dist.distance(a, b);
+                matrix[i][j] = dist;
+                matrix[j][i] = dist;
+            }
+        }
+        matrix
+    }
+
+    /// Find the two closest elements of the BK-tree, with their distance
+    ///
+    /// Returns `None` if the BK-tree holds fewer than two elements. Ties are broken by the order
+    /// of [`iter`](Self::iter). This compares every pair of elements, which takes a time
+    /// quadratic in their number, so it is only meant for small BK-trees, e.g. to spot
+    /// near-duplicates in a dictionary.
+    pub fn closest_pair(&self) -> Option<(&T, &T, isize)> {
+        let words: Vec<&T> = self.iter().collect();
+        let mut best: Option<(&T, &T, isize)> = None;
+        for (i, a) in words.iter().enumerate() {
+            for b in &words[i + 1..] {
+                let dist = self.dist.distance(a, b);
+                if best.is_none_or(|(_, _, best_dist)| dist < best_dist) {
+                    best = Some((a, b, dist));
+                }
+            }
+        }
+        best
+    }
+
+    /// Count how many edges of the BK-tree have each distance value
+    ///
+    /// A heavily skewed histogram indicates a metric producing clustered distances, which leads
+    /// to an unbalanced tree.
+    pub fn edge_distance_histogram(&self) -> BTreeMap<isize, usize> {
+        let mut histogram = BTreeMap::new();
+        let mut queue: Vec<&Node<T>> = self.root.iter().collect();
+        while let Some(n) = queue.pop() {
+            for (dist, child) in &n.children {
+                *histogram.entry(*dist).or_insert(0) += 1;
+                queue.push(child);
+            }
+        }
+        histogram
+    }
+
+    /// Estimate the memory used by the BK-tree, in bytes
+    ///
+    /// This is the size of the BK-tree itself plus the allocated capacity of every node's
+    /// children vector, in which child nodes are stored inline, and of the indices of nodes with
+    /// many children. Heap memory owned by the elements
+    /// themselves (e.g. the buffer of a `String`) is not counted.
+    pub fn memory_usage(&self) -> usize {
+        let mut total = std::mem::size_of::<Self>();
+        let mut queue: Vec<&Node<T>> = self.root.iter().collect();
+        while let Some(n) = queue.pop() {
+            total += n.children.capacity() * std::mem::size_of::<(isize, Node<T>)>();
+            if let Some(ref index) = n.index {
+                total += std::mem::size_of::<HashMap<isize, usize>>()
+                    + index.capacity() * std::mem::size_of::<(isize, usize)>();
+            }
+            queue.extend(n.children.iter().map(|(_, child)| child));
+        }
+        total
+    }
+
+    /// Create an iterator over references of BK-tree elements along with the size of the subtree
+    /// rooted at each of them, including itself, in post-order
+    pub fn iter_subtree_sizes(&self) -> impl Iterator<Item = (&T, usize)> {
+        fn visit<'a, T>(node: &'a Node<T>, sizes: &mut Vec<(&'a T, usize)>) -> usize {
+            let size = 1 + node
+                .children
+                .iter()
+                .map(|(_, child)| visit(child, sizes))
+                .sum::<usize>();
+            sizes.push((&node.word, size));
+            size
+        }
+
+        let mut sizes = Vec::new();
+        if let Some(ref root) = self.root {
+            visit(root, &mut sizes);
+        }
+        sizes.into_iter()
+    }
+
+    /// Turn the BK-tree into an immutable, read-optimized [`FrozenBkTree`]
+    pub fn freeze(self) -> FrozenBkTree<T, D> {
+        self.freeze_with_edges()
+    }
+
+    /// Turn the BK-tree into an immutable, read-optimized [`FrozenBkTree`] storing edge
+    /// distances as `E`
+    ///
+    /// Narrower edge types, e.g. `u8` for distance functions which never exceed 255, make the
+    /// frozen BK-tree smaller. An edge distance out of the range of `E` is a logic error, which
+    /// makes this panic.
+    ///
+    /// ```rust
+    /// use bktree::*;
+    ///
+    /// let mut bk = BkTree::new(HammingDistance);
+    /// bk.insert_all(0..256u32);
+    /// let frozen: FrozenBkTree<u32, HammingDistance, u8> = bk.freeze_with_edges();
+    /// assert_eq!(frozen.find(0, 1).len(), 9);
+    /// ```
+    pub fn freeze_with_edges<E: num::PrimInt>(self) -> FrozenBkTree<T, D, E> {
+        FrozenBkTree::from_root(self.root, self.dist)
+    }
+
+    /// Get the smallest nonzero edge distance of the BK-tree, or `None` if it has no edge
+    pub fn min_positive_edge(&self) -> Option<isize> {
+        self.edge_distance_histogram()
+            .into_keys()
+            .find(|dist| *dist > 0)
+    }
+
+    /// Count how many elements of the BK-tree are at each distance of a given pivot
+    ///
+    /// This computes the distance from every element to the pivot, which helps choosing search
+    /// radii for queries resembling the pivot.
+    pub fn distance_histogram(&self, pivot: &T) -> BTreeMap<isize, usize> {
+        let mut histogram = BTreeMap::new();
+        for word in self.iter() {
+            *histogram
+                .entry(self.dist.distance(word, pivot))
+                .or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    /// Get the greatest common divisor of all edge distances of the BK-tree, or `None` if it
+    /// has no edge
+    ///
+    /// A GCD above 1 means the metric only produces multiples of it on this data, so search
+    /// radii can be coarsened accordingly.
+    pub fn gcd_of_edges(&self) -> Option<isize> {
+        self.edge_distance_histogram()
+            .into_keys()
+            .map(|dist| dist.abs())
+            .reduce(num::integer::gcd)
+    }
+
+    /// Create an iterator over references of BK-tree elements, in no particular order
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut queue = Vec::new();
+        if let Some(ref root) = self.root {
+            queue.push(root);
+        }
+        Iter { queue }
+    }
+
+    /// Consume the BK-tree into an iterator over its elements in breadth-first order, each with
+    /// the distance on the edge to its parent (`None` for the root)
+    ///
+    /// Children are visited in insertion order, so inserting the elements in the same order in
+    /// an empty BK-tree with the same distance function rebuilds an identical BK-tree.
+    pub fn into_bfs(self) -> IntoBfs<T> {
+        IntoBfs {
+            queue: self.root.into_iter().map(|root| (None, root)).collect(),
+        }
+    }
+}
+
+impl<T: Hash, D> Hash for BkTree<T, D> {
+    /// BK-trees are hashed structurally, consistently with their equality
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.root.hash(state);
+    }
+}
+
+impl<T: Clone, D: Clone> Clone for BkTree<T, D> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            dist: self.dist.clone(),
+            dirty: self.dirty.clone(),
+            multiset: self.multiset,
+            index_threshold: self.index_threshold,
+            canonical_dedup: self.canonical_dedup.clone(),
+        }
+    }
+
+    /// Rebuild this BK-tree from `source`, reusing the allocations of the nodes at the same
+    /// positions in both BK-trees
+    fn clone_from(&mut self, source: &Self) {
+        self.root.clone_from(&source.root);
+        self.dist.clone_from(&source.dist);
+        self.dirty.mark_all();
+        self.multiset = source.multiset;
+        self.index_threshold = source.index_threshold;
+        self.canonical_dedup.clone_from(&source.canonical_dedup);
+    }
+}
+
+impl<T: PartialEq, D> PartialEq for BkTree<T, D> {
+    /// BK-trees are compared structurally, ignoring the distance function: trees holding the
+    /// same elements under a different shape, e.g. a different root, are not equal
+    fn eq(&self, other: &Self) -> bool {
+        self.root == other.root
+    }
+}
+
+impl<T: Eq, D> Eq for BkTree<T, D> {}
+
+impl<T, D> IntoIterator for BkTree<T, D> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let mut queue = Vec::new();
+        if let Some(root) = self.root {
+            queue.push(root);
+        }
+        IntoIter { queue }
+    }
+}
+
+/// Iterator over BK-tree elements
+pub struct IntoIter<T> {
+    queue: Vec<Node<T>>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.queue.pop().map(|node| {
+            self.queue.extend(node.children.into_iter().map(|(_, n)| n));
+            node.word
+        })
+    }
+}
+
+/// Iterator over BK-tree elements in breadth-first order, with their distance to their parent
+pub struct IntoBfs<T> {
+    queue: std::collections::VecDeque<(Option<isize>, Node<T>)>,
+}
+
+impl<T> Iterator for IntoBfs<T> {
+    type Item = (T, Option<isize>);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.queue.pop_front().map(|(edge, node)| {
+            self.queue
+                .extend(node.children.into_iter().map(|(k, n)| (Some(k), n)));
+            (node.word, edge)
+        })
+    }
+}
+
+/// Iterator over BK-tree elements by reference in increasing distance to a pivot, see
+/// [`BkTree::iter_by_distance`]
+pub struct IterByDistance<'a, T, D> {
+    dist: &'a D,
+    pivot: &'a T,
+    nodes: Vec<&'a Node<T>>,
+    /// Nodes by lowest possible distance, `false` marking those whose distance is known
+    frontier: BinaryHeap<(Reverse<isize>, Reverse<bool>, Reverse<usize>)>,
+}
+
+impl<'a, T, D> IterByDistance<'a, T, D> {
+    fn push_node(&mut self, node: &'a Node<T>, lower_bound: isize) {
+        self.nodes.push(node);
+        self.frontier.push((
+            Reverse(lower_bound),
+            Reverse(true),
+            Reverse(self.nodes.len() - 1),
+        ));
+    }
+}
+
+impl<'a, T, D: Distance<T>> Iterator for IterByDistance<'a, T, D> {
+    type Item = (&'a T, isize);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (Reverse(bound), Reverse(unvisited), Reverse(i)) = self.frontier.pop()?;
+            let n = self.nodes[i];
+            if !unvisited {
+                return Some((&n.word, bound));
+            }
+            let distance = self.dist.distance(&n.word, self.pivot);
+            self.frontier
+                .push((Reverse(distance), Reverse(false), Reverse(i)));
+            for (arc, child) in &n.children {
+                self.push_node(child, bound.max((arc - distance).abs()));
+            }
+        }
+    }
+}
+
+/// Iterator over BK-tree elements, by reference
+pub struct Iter<'a, T> {
+    queue: Vec<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.queue.pop().map(|node| {
+            self.queue.extend(node.children.iter().map(|(_, n)| n));
+            &node.word
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate bincode;
+
+    use crate::bounded::*;
+    use crate::builder::*;
+    use crate::distance::*;
+    use crate::frozen::*;
+    use crate::index::*;
+    use crate::indexed::*;
+    use crate::interner::*;
+    use crate::keyed::*;
+    use crate::map::*;
+    use crate::mutable::*;
+    use crate::streaming::*;
+    use crate::{BkError, BkTree, NodeSpec};
+    use std::borrow::Cow;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::{HashMap, HashSet};
+    use std::hash::{Hash, Hasher};
+    /// Hamming distance counting its evaluations
+    #[derive(Default)]
+    struct Counting(std::cell::Cell<usize>);
+
+    impl Distance<i32> for Counting {
+        fn distance(&self, a: &i32, b: &i32) -> isize {
+            self.0.set(self.0.get() + 1);
+            HammingDistance.distance(a, b)
+        }
+    }
+
+    /// Case-insensitive Levenshtein distance, a pseudometric telling stored elements from the
+    /// equivalent ones looked up or inserted, counting how many strings it lowercases
+    #[derive(Default, Clone)]
+    struct CaseInsensitive(std::cell::Cell<usize>);
+
+    impl CaseInsensitive {
+        fn lowercase(&self, s: &str) -> String {
+            self.0.set(self.0.get() + 1);
+            s.to_lowercase()
+        }
+    }
+
+    impl<T: AsRef<str> + ?Sized> Distance<T> for CaseInsensitive {
+        fn distance(&self, a: &T, b: &T) -> isize {
+            LevenshteinDistance.distance(&self.lowercase(a.as_ref()), &self.lowercase(b.as_ref()))
+        }
+    }
+
+    impl<T: AsRef<str> + ?Sized> PreparedDistance<T> for CaseInsensitive {
+        type Prepared = String;
+
+        fn prepare(&self, query: &T) -> String {
+            self.lowercase(query.as_ref())
+        }
+
+        fn distance_prepared(&self, prepared: &String, candidate: &T) -> isize {
+            LevenshteinDistance.distance(prepared, &self.lowercase(candidate.as_ref()))
+        }
+    }
+
+    #[test]
+    fn levenshtein_distance_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        bk.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+        let (words, dists): (Vec<&str>, Vec<isize>) = bk.find("bo", 2).into_iter().unzip();
+        assert_eq!(words, ["book", "boo", "boon"]);
+        assert_eq!(dists, [2, 1, 2]);
+    }
+
+    #[test]
+    fn hamming_distance_test() {
+        let mut bk = BkTree::new(HammingDistance);
+        bk.insert_all(vec![0, 4, 5, 14, 15]);
+
+        let (words, dists): (Vec<i32>, Vec<isize>) = bk.find(13, 1).into_iter().unzip();
+        assert_eq!(words, [5, 15]);
+        assert_eq!(dists, [1, 1]);
+    }
+
+    #[test]
+    fn dice_distance_test() {
+        let dice = DiceDistance { scale: 100 };
+        assert_eq!(dice.distance("night", "night"), 0);
+        assert_eq!(dice.distance("night", "nacht"), 75);
+        assert_eq!(dice.distance("abc", "xyz"), 100);
+        assert_eq!(dice.distance("a", "b"), 100);
+
+        let mut bk = BkTree::new(DiceDistance { scale: 100 });
+        bk.insert_all(vec!["night", "nacht", "nights", "day"]);
+        let (words, dists): (Vec<&str>, Vec<isize>) = bk.find("night", 20).into_iter().unzip();
+        assert_eq!(words, ["night", "nights"]);
+        assert_eq!(dists, [0, 11]);
+    }
+
+    #[test]
+    fn manhattan_distance_test() {
+        let mut bk = BkTree::new(ManhattanDistance);
+        bk.insert_all(vec![
+            [0, 0, 0],
+            [1, 2, 3],
+            [-1, 0, 1],
+            [10, 10, 10],
+            [1, 1, 1],
+        ]);
+
+        let (points, dists): (Vec<[i64; 3]>, Vec<isize>) = bk
+            .find([0, 0, 1], 2)
+            .into_iter()
+            .map(|(p, d)| (*p, d))
+            .unzip();
+        assert_eq!(points, [[0, 0, 0], [-1, 0, 1], [1, 1, 1]]);
+        assert_eq!(dists, [1, 1, 2]);
+        assert_eq!(bk.find_nearest([9, 9, 9]), Some((&[10, 10, 10], 3)));
+
+        assert_eq!(
+            ManhattanDistance.checked_distance(&[0, 0, 1], &[1, 1, 1]),
+            Ok(2)
+        );
+        match ManhattanDistance.checked_distance(&[0, 0, 1], &[1, 1]) {
+            Err(BkError::DimensionMismatch { expected, found }) => {
+                assert_eq!((expected, found), (3, 2))
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn lcs_distance_test() {
+        assert_eq!(LcsDistance.distance("book", "book"), 0);
+        assert_eq!(LcsDistance.distance("book", "back"), 4);
+        assert_eq!(LcsDistance.distance("book", "books"), 1);
+        assert_eq!(LcsDistance.distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn iterators_test() {
+        let mut bk = BkTree::new(HammingDistance);
+        bk.insert_all(vec![0, 4, 5, 14, 15]);
+
+        let iter_res: Vec<&i32> = bk.iter().collect();
+        assert_eq!(iter_res, [&0, &15, &14, &5, &4]);
+        let intoiter_res: Vec<i32> = bk.into_iter().collect();
+        assert_eq!(intoiter_res, [0, 15, 14, 5, 4]);
+    }
+
+    #[test]
+    fn find_expanding_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        bk.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+
+        let (words, dists): (Vec<&str>, Vec<isize>) =
+            bk.find_expanding("bo", 3, 10).into_iter().unzip();
+        assert_eq!(words, ["book", "boo", "boon"]);
+        assert_eq!(dists, [2, 1, 2]);
+
+        let (words, _): (Vec<&str>, Vec<isize>) =
+            bk.find_expanding("bo", 1, 10).into_iter().unzip();
+        assert_eq!(words, ["boo"]);
+
+        assert!(bk.find_expanding("zzzzzzzz", 1, 2).is_empty());
+    }
+
+    #[test]
+    fn edge_distance_histogram_test() {
+        let mut bk = BkTree::new(HammingDistance);
+        assert!(bk.edge_distance_histogram().is_empty());
+
+        // 0 -> {4 (1), 5 (2), 14 (3), 15 (4)}, and 1 hangs off 4 under distance 2
+        bk.insert_all(vec![0, 4, 5, 14, 15, 1]);
+        let histogram: Vec<(isize, usize)> = bk.edge_distance_histogram().into_iter().collect();
+        assert_eq!(histogram, [(1, 1), (2, 2), (3, 1), (4, 1)]);
+    }
+
+    #[test]
+    fn boxed_str_test() {
+        let mut bk: BkTree<Box<str>> = BkTree::new(LevenshteinDistance);
+        bk.insert_all(
+            vec![
+                "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+            ]
+            .into_iter()
+            .map(Box::from),
+        );
+
+        let (words, dists): (Vec<&str>, Vec<isize>) = bk
+            .find_borrowed("bo", 2)
+            .into_iter()
+            .map(|(w, d)| (&**w, d))
+            .unzip();
+        assert_eq!(words, ["book", "boo", "boon"]);
+        assert_eq!(dists, [2, 1, 2]);
+
+        let words: Vec<&str> = bk
+            .find("cape".into(), 0)
+            .into_iter()
+            .map(|(w, _)| &**w)
+            .collect();
+        assert_eq!(words, ["cape"]);
+    }
+
+    #[test]
+    fn contains_within_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        assert!(!bk.contains_within(&"book", 10));
+
+        bk.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+        assert!(bk.contains_within(&"bo", 1));
+        assert!(bk.contains_within(&"carts", 1));
+        assert!(!bk.contains_within(&"bo", 0));
+        assert!(!bk.contains_within(&"zzzz", 3));
+    }
+
+    #[test]
+    fn contains_within_batch_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        assert_eq!(
+            bk.contains_within_batch(&["book", "bo"], 10),
+            [false, false]
+        );
+
+        bk.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+        assert_eq!(
+            bk.contains_within_batch(&["bo", "zzzz", "carts", "xyz", "cak"], 1),
+            [true, false, true, false, true]
+        );
+        assert!(bk.contains_within_batch(&[], 1).is_empty());
+    }
+
+    #[test]
+    fn interned_test() {
+        let mut bk: InternedBkTree = BkTree::new(Interner::new(LevenshteinDistance));
+        let words = vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ];
+        let ids: Vec<SymbolId> = words.iter().map(|w| bk.insert_str(w)).collect();
+        for (id, word) in ids.iter().zip(&words) {
+            assert_eq!(bk.resolve(*id), *word);
+        }
+        assert_eq!(bk.insert_str("boon"), ids[3]);
+        assert_eq!(bk.iter().count(), words.len());
+
+        let (found, dists): (Vec<&str>, Vec<isize>) = bk.find_str("bo", 2).into_iter().unzip();
+        assert_eq!(found, ["book", "boo", "boon"]);
+        assert_eq!(dists, [2, 1, 2]);
+    }
+
+    #[test]
+    fn find_sorted_test() {
+        let words = vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ];
+        let mut bk = BkTree::new(LevenshteinDistance);
+        bk.insert_all(words.clone());
+        let mut reversed = BkTree::new(LevenshteinDistance);
+        reversed.insert_all(words.into_iter().rev());
+
+        let (found, dists): (Vec<&str>, Vec<isize>) = bk.find_sorted("ca", 3).into_iter().unzip();
+        assert_eq!(found, ["cake", "cape", "cart", "boo", "cook"]);
+        assert_eq!(dists, [2, 2, 2, 3, 3]);
+        assert_eq!(bk.find_sorted("ca", 3), reversed.find_sorted("ca", 3));
+    }
+
+    #[test]
+    fn map_find_mut_test() {
+        let mut bk = BkMap::new(LevenshteinDistance);
+        bk.insert("book", 1);
+        bk.insert("boo", 2);
+        bk.insert("boon", 3);
+        bk.insert("cake", 4);
+
+        let found = bk.find_mut(&"bo", 2);
+        assert_eq!(found.len(), 3);
+        for (_, value, dist) in found {
+            *value *= 10 + dist as i32;
+        }
+        assert_eq!(bk.get(&"book"), Some(&12));
+        assert_eq!(bk.get(&"boo"), Some(&22));
+        assert_eq!(bk.get(&"boon"), Some(&36));
+        assert_eq!(bk.get(&"cake"), Some(&4));
+        assert!(bk.find_mut(&"not here", 0).is_empty());
+    }
+
+    #[test]
+    fn map_test() {
+        let mut bk = BkMap::new(LevenshteinDistance);
+        for (i, word) in ["book", "books", "boo", "boon", "cook"].iter().enumerate() {
+            bk.insert(*word, i);
+        }
+        assert_eq!(bk.get(&"boon"), Some(&3));
+        assert_eq!(bk.get(&"cake"), None);
+        assert_eq!(bk.find(&"bo", 1), [(&"boo", &2, 1)]);
+        assert_eq!(bk.iter().count(), 5);
+    }
+
+    #[test]
+    fn map_index_test() {
+        let mut bk = BkMap::new(LevenshteinDistance);
+        bk.insert("book", 1);
+        bk.insert("boo", 2);
+        assert_eq!(bk[&"book"], 1);
+        assert_eq!(bk[&"boo"], 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "no key at distance 0 in the BK-map")]
+    fn map_index_missing_test() {
+        let mut bk = BkMap::new(LevenshteinDistance);
+        bk.insert("book", 1);
+        let _ = bk[&"boo"];
+    }
+
+    #[test]
+    fn find_ranked_test() {
+        let mut bk = BkMap::new(LevenshteinDistance);
+        bk.insert("boo", 1.0);
+        bk.insert("book", 10.0);
+        bk.insert("cook", 0.0);
+
+        let ranked: Vec<&str> = bk
+            .find_ranked(&"bo", 2, 0.0)
+            .into_iter()
+            .map(|(k, _, _)| *k)
+            .collect();
+        assert_eq!(ranked, ["boo", "book"]);
+
+        let ranked: Vec<&str> = bk
+            .find_ranked(&"bo", 2, 0.5)
+            .into_iter()
+            .map(|(k, _, _)| *k)
+            .collect();
+        assert_eq!(ranked, ["book", "boo"]);
+    }
+
+    #[test]
+    fn hash_test() {
+        fn hash_of<T: Hash>(val: &T) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            val.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        // Same structure with the root's children inserted in different orders
+        let mut a = BkTree::new(HammingDistance);
+        a.insert_all(vec![0, 4, 5, 14, 15]);
+        let mut b = BkTree::new(HammingDistance);
+        b.insert_all(vec![0, 15, 5, 14, 4]);
+        assert!(a == b);
+        assert_eq!(hash_of(&a), hash_of(&b));
+
+        let mut c = BkTree::new(HammingDistance);
+        c.insert_all(vec![0, 4, 5, 14]);
+        assert!(a != c);
+        assert_ne!(hash_of(&a), hash_of(&c));
+
+        // Duplicates below the same edge of a multiset BK-tree
+        let mut a = BkTree::with_multiset(CaseInsensitive::default(), true);
+        a.insert_all(vec!["book", "Book", "BOOK", "boo"]);
+        let mut b = BkTree::with_multiset(CaseInsensitive::default(), true);
+        b.insert_all(vec!["book", "BOOK", "boo", "Book"]);
+        assert!(a == b);
+        assert_eq!(hash_of(&a), hash_of(&b));
+    }
+
+    #[test]
+    fn find_any_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        bk.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+
+        // "boo" and "cook" are found by both queries, with distances 0 and 2
+        let mut found = bk.find_any(&["boo", "cook"], 2);
+        found.sort();
+        assert_eq!(
+            found,
+            [
+                (&"boo", 0),
+                (&"book", 1),
+                (&"books", 2),
+                (&"boon", 1),
+                (&"cook", 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn find_nearest_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        assert_eq!(bk.find_nearest("bo"), None);
+
+        bk.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+        assert_eq!(bk.find_nearest("bo"), Some((&"boo", 1)));
+        assert_eq!(bk.find_nearest("cape"), Some((&"cape", 0)));
+        assert_eq!(bk.find_nearest("carts"), Some((&"cart", 1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "edge distance out of range")]
+    fn freeze_with_narrow_edges_test() {
+        let mut bk = BkTree::new(ManhattanDistance);
+        bk.insert_all(vec![[0], [300]]);
+        let _: FrozenBkTree<[i64; 1], _, u8> = bk.freeze_with_edges();
+    }
+
+    #[test]
+    fn freeze_test() {
+        let words = vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ];
+        let mut bk = BkTree::new(LevenshteinDistance);
+        bk.insert_all(words.clone());
+        let frozen: FrozenBkTree<&str> = bk.clone().freeze();
+
+        for query in &["bo", "ca", "book", "zzzz"] {
+            for max_dist in 0..4 {
+                assert_eq!(frozen.find(query, max_dist), bk.find(query, max_dist));
+            }
+            assert_eq!(frozen.find_nearest(query), bk.find_nearest(query));
+        }
+
+        let small: FrozenBkTree<&str, _, u8> = bk.clone().freeze_with_edges();
+        for query in &["bo", "ca", "book", "zzzz"] {
+            for max_dist in 0..4 {
+                assert_eq!(small.find(query, max_dist), bk.find(query, max_dist));
+            }
+            assert_eq!(small.find_nearest(query), bk.find_nearest(query));
+        }
+        assert!(small.memory_usage() < frozen.memory_usage());
+
+        let mut frozen_words: Vec<&str> = frozen.iter().copied().collect();
+        frozen_words.sort();
+        let mut bk_words: Vec<&str> = bk.iter().copied().collect();
+        bk_words.sort();
+        assert_eq!(frozen_words, bk_words);
+
+        let shared = std::sync::Arc::new(frozen);
+        let handle = {
+            let shared = shared.clone();
+            std::thread::spawn(move || shared.find("bo", 2).len())
+        };
+        assert_eq!(handle.join().unwrap(), 3);
+    }
+
+    #[test]
+    fn get_or_insert_test() {
+        let mut bk: BkTree<String> = BkTree::new(LevenshteinDistance);
+        bk.insert_all(vec!["book".to_string(), "boo".to_string()]);
+
+        let first: *const String = bk.get_or_insert("cake".to_string());
+        let second: *const String = bk.get_or_insert("cake".to_string());
+        assert_eq!(first, second);
+        assert_eq!(bk.get_or_insert("boo".to_string()), "boo");
+        assert_eq!(bk.iter().count(), 3);
+
+        let mut empty: BkTree<&str> = BkTree::new(LevenshteinDistance);
+        assert_eq!(*empty.get_or_insert("book"), "book");
+        assert_eq!(*empty.get_or_insert("book"), "book");
+        assert_eq!(empty.iter().count(), 1);
+    }
+
+    #[test]
+    fn find_dedup_by_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        bk.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+
+        // Keyed by first letter, only the closest "b" and "c" words remain
+        let mut found = bk.find_dedup_by("coo", 2, |w| w.chars().next());
+        found.sort();
+        assert_eq!(found, [(&"boo", 1), (&"cook", 1)]);
+    }
+
+    #[test]
+    fn memory_usage_test() {
+        let mut bk = BkTree::new(HammingDistance);
+        let mut usage = bk.memory_usage();
+        assert_eq!(usage, std::mem::size_of::<BkTree<i32, HammingDistance>>());
+
+        for i in 0..64 {
+            bk.insert(i);
+            let new_usage = bk.memory_usage();
+            assert!(new_usage >= usage);
+            usage = new_usage;
+        }
+        assert!(usage > std::mem::size_of::<BkTree<i32, HammingDistance>>());
+    }
+
+    #[test]
+    fn len_test() {
+        let mut bk = BkTree::new(HammingDistance);
+        assert!(bk.is_empty());
+        assert_eq!(bk.len(), 0);
+
+        bk.insert_all(vec![0, 4, 5, 14, 15, 4]);
+        assert!(!bk.is_empty());
+        assert_eq!(bk.len(), 5);
+    }
+
+    #[test]
+    fn nearest_neighbor_index_test() {
+        fn load(index: &mut dyn NearestNeighborIndex<i32>) {
+            for i in [0, 4, 5, 14, 15] {
+                index.insert(i);
+            }
+        }
+
+        let mut bk = BkTree::new(HammingDistance);
+        load(&mut bk);
+
+        let index: &dyn NearestNeighborIndex<i32> = &bk;
+        assert_eq!(index.len(), bk.len());
+        assert_eq!(index.find(13, 1), bk.find(13, 1));
+        assert_eq!(index.find_nearest(13), bk.find_nearest(13));
+    }
+
+    #[test]
+    fn find_prepared_test() {
+        let mut bk = BkTree::new(CaseInsensitive::default());
+        bk.insert_all(
+            [
+                "Book", "books", "BOO", "boon", "Cook", "cake", "cape", "cart",
+            ]
+            .iter()
+            .map(|w| w.to_string()),
+        );
+
+        bk.dist.0.set(0);
+        let naive = bk.find("BO".to_string(), 2);
+        let naive_normalizations = bk.dist.0.get();
+        bk.dist.0.set(0);
+        let prepared = bk.find_prepared("BO".to_string(), 2);
+        assert_eq!(prepared, naive);
+        assert!(bk.dist.0.get() < naive_normalizations);
+
+        let mut bk = BkTree::new(LevenshteinDistance);
+        bk.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+        for max_dist in 0..4 {
+            assert_eq!(bk.find_prepared("ca", max_dist), bk.find("ca", max_dist));
+        }
+
+        // Any metric can be used through `Unprepared`
+        let mut bk = BkTree::new(Unprepared(DiceDistance { scale: 100 }));
+        bk.insert_all(vec!["night", "nacht", "nights", "knight"]);
+        for max_dist in [0, 40, 70, 100] {
+            assert_eq!(
+                bk.find_prepared("night", max_dist),
+                bk.find("night", max_dist)
+            );
+        }
+        assert_eq!(bk.find_prepared("night", 40).len(), 3);
+    }
+
+    #[test]
+    fn children_of_test() {
+        let mut bk = BkTree::new(HammingDistance);
+        assert_eq!(bk.children_of(&0), None);
+
+        bk.insert_all(vec![0, 4, 5, 14, 15, 1]);
+        assert_eq!(bk.children_of(&0), Some(4));
+        assert_eq!(bk.children_of(&4), Some(1));
+        assert_eq!(bk.children_of(&1), Some(0));
+        assert_eq!(bk.children_of(&7), None);
+    }
+
+    #[test]
+    fn remove_within_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        bk.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+
+        assert_eq!(bk.remove_within(&"boo", 1), 3);
+        assert_eq!(bk.len(), 5);
+        assert!(!bk.contains_within(&"boo", 1));
+        for word in &["books", "cook", "cake", "cape", "cart"] {
+            assert_eq!(bk.find(word, 0), [(word, 0)]);
+        }
+        assert_eq!(bk.remove_within(&"boo", 1), 0);
+
+        // "book" was the root, its re-inserted descendants can still be removed and found
+        assert_eq!(bk.remove_within(&"books", 0), 1);
+        let mut words: Vec<&str> = bk.iter().copied().collect();
+        words.sort();
+        assert_eq!(words, ["cake", "cape", "cart", "cook"]);
+        for word in &words {
+            assert_eq!(bk.find(word, 0), [(word, 0)]);
+        }
+    }
+
+    #[test]
+    fn insert_all_counted_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        assert!(bk.insert("book"));
+        assert!(!bk.insert("book"));
+
+        let added = bk.insert_all_counted(vec!["book", "boo", "cake", "boo", "cape", "cake"]);
+        assert_eq!(added, 3);
+        assert_eq!(bk.len(), 4);
+    }
+
+    #[test]
+    fn bounded_bktree_test() {
+        let mut bk = BoundedBkTree::new(LevenshteinDistance, 3);
+        assert!(bk.insert("book"));
+        assert!(bk.insert("cake"));
+        assert!(bk.insert("cart"));
+        assert!(!bk.insert("book"));
+        assert_eq!(bk.len(), 3);
+
+        // "cake" was inserted before "cart" but is used more recently
+        assert_eq!(bk.find("cake", 0), [(&"cake", 0)]);
+        assert_eq!(bk.find("boo", 1), [(&"book", 1)]);
+        assert!(bk.insert("cape"));
+        assert_eq!(bk.len(), 3);
+        let mut words: Vec<&str> = bk.iter().copied().collect();
+        words.sort();
+        assert_eq!(words, ["book", "cake", "cape"]);
+
+        // "book" is now the least recently used
+        bk.find("ca", 2);
+        assert!(bk.insert("boon"));
+        let mut words: Vec<&str> = bk.iter().copied().collect();
+        words.sort();
+        assert_eq!(words, ["boon", "cake", "cape"]);
+        assert_eq!(bk.len(), bk.capacity());
+
+        let mut empty = BoundedBkTree::new(LevenshteinDistance, 0);
+        assert!(!empty.insert("book"));
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn merge_iter_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        bk.insert_all(vec!["book", "boo", "cake"]);
+
+        assert_eq!(
+            bk.merge_iter(vec!["cake", "cape", "boo", "cart", "cape"]),
+            2
+        );
+        assert_eq!(bk.len(), 5);
+        assert_eq!(bk.merge_iter(vec!["book", "cart"]), 0);
+        assert_eq!(bk.merge_iter(Vec::new()), 0);
+        assert_eq!(bk.len(), 5);
+    }
+
+    #[test]
+    fn keyed_test() {
+        struct Record {
+            name: &'static str,
+            id: u32,
+        }
+
+        let mut bk = KeyedBkTree::new(|r: &Record| r.name.to_lowercase(), LevenshteinDistance);
+        bk.insert(Record {
+            name: "Book",
+            id: 1,
+        });
+        bk.insert(Record { name: "boo", id: 2 });
+        bk.insert(Record {
+            name: "Cake",
+            id: 3,
+        });
+        assert!(!bk.insert(Record {
+            name: "BOOK",
+            id: 4
+        }));
+        assert_eq!(bk.len(), 3);
+
+        let found: Vec<(u32, isize)> = bk
+            .find(&"bok".to_string(), 1)
+            .into_iter()
+            .map(|(r, d)| (r.id, d))
+            .collect();
+        assert_eq!(found, [(1, 1), (2, 1)]);
+    }
+
+    #[test]
+    fn cow_str_test() {
+        let mut bk: BkTree<Cow<str>> = BkTree::new(LevenshteinDistance);
+        bk.insert_all(vec!["book", "boo", "cake"].into_iter().map(Cow::Borrowed));
+        bk.insert_all(
+            vec!["boon", "cape"]
+                .into_iter()
+                .map(|w| Cow::Owned(w.to_string())),
+        );
+
+        let (words, dists): (Vec<&str>, Vec<isize>) = bk
+            .find_borrowed("bo", 2)
+            .into_iter()
+            .map(|(w, d)| (w.as_ref(), d))
+            .unzip();
+        assert_eq!(words, ["book", "boo", "boon"]);
+        assert_eq!(dists, [2, 1, 2]);
+
+        let words: Vec<&str> = bk
+            .find(Cow::Borrowed("cake"), 1)
+            .into_iter()
+            .map(|(w, _)| w.as_ref())
+            .collect();
+        assert_eq!(words, ["cake", "cape"]);
+    }
+
+    #[test]
+    fn insert_all_reporting_collisions_test() {
+        // Pseudometric only looking at the last decimal digit
+        struct LastDigit;
+        impl Distance<i32> for LastDigit {
+            fn distance(&self, a: &i32, b: &i32) -> isize {
+                (a % 10 - b % 10).abs() as isize
+            }
+        }
+
+        let mut bk = BkTree::new(LastDigit);
+        let collisions = bk.insert_all_reporting_collisions(vec![1, 2, 11, 3, 2, 23]);
+        assert_eq!(collisions, [11, 2, 23]);
+        assert_eq!(bk.len(), 3);
+    }
+
+    #[test]
+    fn find_indexed_test() {
+        let words = vec![
+            "book", "books", "boo", "book", "boon", "cook", "cake", "cape", "cart",
+        ];
+        let mut bk = IndexedBkTree::new(LevenshteinDistance);
+        bk.insert_all(words.clone());
+
+        let mut found = bk.find_indexed("bo", 2);
+        found.sort();
+        assert_eq!(found, [(0, 2), (2, 1), (4, 2)]);
+        for (index, _) in found {
+            assert!(words[index].starts_with("bo"));
+        }
+
+        assert_eq!(bk.insert("cooks"), 9);
+        assert_eq!(bk.find("cooks", 0), [(&"cooks", 9, 0)]);
+    }
+
+    #[test]
+    fn find_approx_test() {
+        let mut bk = BkTree::new(Counting::default());
+        bk.insert_all(0..256);
+
+        bk.dist.0.set(0);
+        let exact = bk.find(0b1010_1010, 3);
+        let exact_evals = bk.dist.0.get();
+        assert_eq!(bk.find_approx(0b1010_1010, 3, 0), exact);
+
+        bk.dist.0.set(0);
+        let approx = bk.find_approx(0b1010_1010, 3, 2);
+        assert!(bk.dist.0.get() < exact_evals);
+        assert!(approx.len() <= exact.len());
+        assert!(approx.iter().all(|found| exact.contains(found)));
+    }
+
+    #[test]
+    fn find_nearest_until_test() {
+        let mut bk = BkTree::new(Counting::default());
+        assert_eq!(bk.find_nearest_until(1, 2), None);
+        bk.insert_all((0..256).step_by(3));
+
+        // Any element is good enough, so the search stops at the root
+        bk.dist.0.set(0);
+        assert_eq!(bk.find_nearest_until(0b1010_1010, 8), Some((&0, 4)));
+        assert_eq!(bk.dist.0.get(), 1);
+
+        // No element is at distance 0, so the search runs until the closest element is found
+        bk.dist.0.set(0);
+        let full = bk.find_nearest_until(0b1010_1010, 0);
+        assert!(bk.dist.0.get() > 1);
+        assert_eq!(full.map(|(_, dist)| dist), Some(1));
+
+        for query in [1, 77, 128, 250] {
+            let nearest = bk.find_nearest(query).map(|(_, dist)| dist);
+            assert_eq!(
+                bk.find_nearest_until(query, -1).map(|(_, dist)| dist),
+                nearest
+            );
+        }
+    }
+
+    #[test]
+    fn eq_test() {
+        let mut a = BkTree::new(HammingDistance);
+        a.insert_all(vec![0, 4, 5, 14, 15]);
+        let mut b = BkTree::new(HammingDistance);
+        b.insert_all(vec![0, 15, 14, 5, 4]);
+        let mut c = BkTree::new(HammingDistance);
+        c.insert_all(vec![4, 0, 5, 14, 15]);
+
+        let set: HashSet<BkTree<i32, HammingDistance>> = vec![a, b, c].into_iter().collect();
+        assert_eq!(set.len(), 2);
+
+        // Children sharing an edge in a multiset BK-tree are matched one to one
+        let mut a = BkTree::with_multiset(CaseInsensitive::default(), true);
+        a.insert_all(vec!["a", "A", "a"]);
+        let mut b = BkTree::with_multiset(CaseInsensitive::default(), true);
+        b.insert_all(vec!["a", "A", "A"]);
+        assert!(a != b);
+        assert!(b != a);
+        let mut c = BkTree::with_multiset(CaseInsensitive::default(), true);
+        c.insert_all(vec!["a", "a", "A"]);
+        assert!(a == c);
+        assert!(c == a);
+    }
+
+    #[test]
+    fn approx_diameter_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        assert_eq!(bk.approx_diameter(), 0);
+
+        bk.insert_all(vec!["book", "books", "boo", "cook", "cart"]);
+        // "books" and "cart" are 5 edits apart
+        assert_eq!(bk.approx_diameter(), 5);
+    }
+
+    #[test]
+    fn split_off_test() {
+        let mut bk = BkTree::new(HammingDistance);
+        bk.insert_all(0..32);
+
+        let even = bk.split_off(|i| i % 2 == 0);
+        let mut even_words: Vec<i32> = even.iter().copied().collect();
+        even_words.sort();
+        assert_eq!(even_words, (0..32).step_by(2).collect::<Vec<i32>>());
+        let mut odd_words: Vec<i32> = bk.iter().copied().collect();
+        odd_words.sort();
+        assert_eq!(odd_words, (1..32).step_by(2).collect::<Vec<i32>>());
+
+        for i in 0..32 {
+            let (tree, other) = if i % 2 == 0 {
+                (&even, &bk)
+            } else {
+                (&bk, &even)
+            };
+            assert_eq!(tree.find(i, 0), [(&i, 0)]);
+            assert!(other.find(i, 0).is_empty());
+        }
+        assert_eq!(even.find(0, 1).len(), 5);
+        assert_eq!(bk.find(0, 1), [(&1, 1)]);
+
+        // Duplicates of a multiset BK-tree are moved along
+        let mut bk = BkTree::with_multiset(LevenshteinDistance, true);
+        bk.insert_all(vec!["book", "book", "boo", "cake", "book"]);
+        let books = bk.split_off(|w| w.starts_with('b'));
+        assert_eq!(books.len(), 4);
+        assert_eq!(books.find("book", 0).len(), 3);
+        assert_eq!(bk.len(), 1);
+    }
+
+    #[test]
+    fn iter_by_distance_test() {
+        let mut bk = BkTree::new(Counting::default());
+        bk.insert_all(0..512);
+        let pivot = 0b1_0110_1101;
+
+        let distances: Vec<isize> = bk.iter_by_distance(&pivot).map(|(_, d)| d).collect();
+        assert!(distances.windows(2).all(|w| w[0] <= w[1]));
+        let mut expected: Vec<isize> = bk
+            .find_all_by_distance(pivot)
+            .into_iter()
+            .map(|(_, d)| d)
+            .collect();
+        expected.sort();
+        assert_eq!(distances, expected);
+
+        bk.dist.0.set(0);
+        let close: Vec<(&i32, isize)> = bk
+            .iter_by_distance(&pivot)
+            .take_while(|(_, d)| *d < 2)
+            .collect();
+        assert_eq!(close.len(), 1 + 9);
+        assert!(close
+            .iter()
+            .all(|(w, d)| HammingDistance.distance(*w, &pivot) == *d));
+        assert!(bk.dist.0.get() < 512);
+
+        let empty = BkTree::new(HammingDistance);
+        assert_eq!(empty.iter_by_distance(&0).next(), None);
+    }
+
+    #[test]
+    fn find_all_by_distance_test() {
+        let mut bk = BkTree::new(HammingDistance);
+        bk.insert_all(vec![0, 4, 5, 14, 15]);
+
+        let found = bk.find_all_by_distance(13);
+        assert_eq!(found.len(), 5);
+        assert!(found.windows(2).all(|w| w[0].1 <= w[1].1));
+        let dists: Vec<isize> = found.iter().map(|(_, d)| *d).collect();
+        assert_eq!(dists, [1, 1, 2, 2, 3]);
+    }
+
+    #[test]
+    fn index_threshold_test() {
+        let words: Vec<[i64; 1]> = (0..20).map(|i| [i]).collect();
+        let mut linear = BkTree::with_index_threshold(ManhattanDistance, usize::MAX);
+        let mut indexed = BkTree::with_index_threshold(ManhattanDistance, 4);
+
+        // Every element is a child of the root, which gets indexed past 4 children
+        for (i, word) in words.iter().enumerate() {
+            assert!(linear.insert(*word));
+            assert!(indexed.insert(*word));
+            assert_eq!(indexed.root.as_ref().unwrap().index.is_some(), i > 4);
+        }
+        assert!(linear.root.as_ref().unwrap().index.is_none());
+        assert!(!indexed.insert([7]));
+        assert_eq!(indexed.get_or_insert([12]), &[12]);
+        assert_eq!(indexed.try_insert([-3]), Ok(true));
+        assert_eq!(linear.try_insert([-3]), Ok(true));
+
+        assert!(linear == indexed);
+        for query in [[-5], [0], [7], [19], [30]] {
+            assert_eq!(linear.find(query, 2), indexed.find(query, 2));
+            assert_eq!(indexed.children_of(&query), linear.children_of(&query));
+        }
+        assert!(indexed.memory_usage() > linear.memory_usage());
+
+        // Removing children below the threshold drops the index
+        assert_eq!(indexed.remove_within(&[10], 8), 17);
+        assert!(indexed.root.as_ref().unwrap().index.is_none());
+        assert!(indexed.contains_exact(&[19]));
+    }
+
+    #[test]
+    fn multiset_test() {
+        let mut bk = BkTree::with_multiset(LevenshteinDistance, true);
+        assert!(bk.insert("book"));
+        assert!(bk.insert("boo"));
+        assert!(bk.insert("book"));
+        assert!(bk.insert("book"));
+        assert_eq!(bk.len(), 4);
+        assert_eq!(bk.iter().filter(|w| **w == "book").count(), 3);
+        assert_eq!(bk.clone().into_iter().filter(|w| *w == "book").count(), 3);
+        assert_eq!(bk.find("book", 0).len(), 3);
+        assert_eq!(bk.find("bo", 1), [(&"boo", 1)]);
+        assert_eq!(bk.find("bo", 2).len(), 4);
+
+        // Duplicates of non-root elements are kept too
+        assert!(bk.insert("boo"));
+        assert_eq!(bk.find("boo", 0).len(), 2);
+        assert_eq!(bk.len(), 5);
+
+        assert_eq!(bk.remove_within(&"book", 0), 3);
+        assert_eq!(bk.len(), 2);
+
+        let mut set = BkTree::with_multiset(LevenshteinDistance, false);
+        set.insert_all(vec!["book", "book", "book"]);
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn try_insert_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        assert_eq!(bk.try_insert("book"), Ok(true));
+        assert_eq!(bk.try_insert("boo"), Ok(true));
+        assert_eq!(bk.try_insert("book"), Ok(false));
+        assert_eq!(bk.try_insert("boon"), Ok(true));
+
+        let mut expected = BkTree::new(LevenshteinDistance);
+        expected.insert_all(vec!["book", "boo", "boon"]);
+        assert!(bk == expected);
+    }
+
+    #[test]
+    fn insert_root_test() {
+        let mut bk = BkTree::new(Counting::default());
+        bk.insert_all(0..64);
+        let usage = bk.memory_usage();
+
+        bk.dist.0.set(0);
+        assert!(!bk.insert(0));
+        assert_eq!(bk.dist.0.get(), 1);
+        assert_eq!(bk.len(), 64);
+        assert_eq!(bk.memory_usage(), usage);
+    }
+
+    #[test]
+    fn diff_test() {
+        let mut a = BkTree::new(LevenshteinDistance);
+        a.insert_all(vec!["book", "books", "boo", "boon", "cook"]);
+        let mut b = BkTree::new(LevenshteinDistance);
+        b.insert_all(vec!["cake", "boo", "cook", "cape", "book"]);
+
+        let (mut only_a, mut only_b) = a.diff(&b);
+        only_a.sort();
+        only_b.sort();
+        assert_eq!(only_a, [&"books", &"boon"]);
+        assert_eq!(only_b, [&"cake", &"cape"]);
+
+        assert_eq!(a.diff(&a), (vec![], vec![]));
+        let empty = BkTree::new(LevenshteinDistance);
+        assert_eq!(empty.diff(&a).1.len(), 5);
+    }
+
+    #[test]
+    fn pairwise_distances_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        assert!(bk.pairwise_distances().is_empty());
+
+        bk.insert_all(vec!["book", "boo", "cake", "cape", "cart"]);
+        let words: Vec<&str> = bk.iter().copied().collect();
+        let matrix = bk.pairwise_distances();
+        assert_eq!(matrix.len(), words.len());
+        for i in 0..words.len() {
+            assert_eq!(matrix[i].len(), words.len());
+            assert_eq!(matrix[i][i], 0);
+            for j in 0..words.len() {
+                assert_eq!(matrix[i][j], matrix[j][i]);
+                assert_eq!(
+                    matrix[i][j],
+                    LevenshteinDistance.distance(words[i], words[j])
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn children_words_of_test() {
+        let mut bk = BkTree::new(HammingDistance);
+        assert_eq!(bk.children_words_of(&0), None);
+
+        bk.insert_all(vec![0, 4, 5, 14, 15, 1]);
+        assert_eq!(
+            bk.children_words_of(&0),
+            Some(vec![(&4, 1), (&5, 2), (&14, 3), (&15, 4)])
+        );
+        assert_eq!(bk.children_words_of(&4), Some(vec![(&1, 2)]));
+        assert_eq!(bk.children_words_of(&1), Some(vec![]));
+        assert_eq!(bk.children_words_of(&2), None);
+    }
+
+    #[test]
+    fn iter_subtree_sizes_test() {
+        let mut bk = BkTree::new(HammingDistance);
+        assert_eq!(bk.iter_subtree_sizes().count(), 0);
+
+        bk.insert_all(vec![0, 4, 5, 14, 15, 1]);
+        let sizes: Vec<(&i32, usize)> = bk.iter_subtree_sizes().collect();
+        assert_eq!(
+            sizes,
+            [(&1, 1), (&4, 2), (&5, 1), (&14, 1), (&15, 1), (&0, 6)]
+        );
+    }
+
+    #[test]
+    fn from_sorted_unique_test() {
+        let sorted = vec![
+            "boo", "book", "books", "boon", "cake", "cape", "cart", "cook",
+        ];
+        let bk = BkTree::from_sorted_unique(LevenshteinDistance, sorted.clone());
+        let mut bisected = BkTree::new(LevenshteinDistance);
+        bisected.insert_all(vec![
+            "cake", "books", "cart", "book", "boon", "cape", "cook", "boo",
+        ]);
+        assert!(bk == bisected);
+        assert!(sorted.iter().all(|word| bk.contains_exact(word)));
+        assert_eq!(bk.len(), sorted.len());
+
+        // "a…abc…c" with a growing suffix of "c": each word is at the same distance of all the
+        // words after it, so inserting them in order builds a single chain
+        let sorted: Vec<String> = (0..32)
+            .map(|j| format!("{}b{}", "a".repeat(31 - j), "c".repeat(j)))
+            .collect();
+        assert!(sorted.windows(2).all(|w| w[0] < w[1]));
+        let bk = BkTree::from_sorted_unique(SuffixDistance, sorted.clone());
+        let mut naive = BkTree::new(SuffixDistance);
+        naive.insert_all(sorted.clone());
+        assert_eq!(naive.depth(), 31);
+        assert_eq!(bk.depth(), 4);
+        assert_eq!(bk.len(), sorted.len());
+        assert!(sorted.iter().all(|word| bk.contains_exact(word)));
+    }
+
+    #[test]
+    fn from_root_test() {
+        let leaf = |word| NodeSpec {
+            word,
+            children: Vec::new(),
+        };
+        let spec = NodeSpec {
+            word: 0,
+            children: vec![
+                (
+                    1,
+                    NodeSpec {
+                        word: 4,
+                        children: vec![(2, leaf(1))],
+                    },
+                ),
+                (2, leaf(5)),
+                (3, leaf(14)),
+                (4, leaf(15)),
+            ],
+        };
+        let bk = BkTree::from_root(HammingDistance, spec.clone()).unwrap();
+
+        let mut inserted = BkTree::new(HammingDistance);
+        inserted.insert_all(vec![0, 4, 5, 14, 15, 1]);
+        assert!(bk == inserted);
+        assert_eq!(bk.find(13, 1), inserted.find(13, 1));
+
+        let mut wrong_edge = spec.clone();
+        wrong_edge.children[1].0 = 3;
+        assert_eq!(
+            BkTree::from_root(HammingDistance, wrong_edge).err(),
+            Some(BkError::CorruptStructure {
+                edge: 3,
+                distance: 2
+            })
+        );
+
+        // 1 is at distance 1 of the root, not 2
+        let mut misplaced = spec;
+        misplaced.children[0].1.children.clear();
+        misplaced.children[1].1.children.push((3, leaf(1)));
+        match BkTree::from_root(HammingDistance, misplaced) {
+            Err(BkError::CorruptStructure { edge, distance }) => {
+                assert_eq!((edge, distance), (2, 1));
+            }
+            _ => panic!("expected a corrupt structure error"),
+        }
+
+        let mut duplicate = NodeSpec {
+            word: 0,
+            children: vec![(1, leaf(1)), (1, leaf(2))],
+        };
+        assert_eq!(
+            BkTree::from_root(HammingDistance, duplicate.clone()).err(),
+            Some(BkError::InvalidEdge { edge: 1 })
+        );
+        duplicate.children[1].0 = 0;
+        assert_eq!(
+            BkTree::from_root(HammingDistance, duplicate).err(),
+            Some(BkError::InvalidEdge { edge: 0 })
+        );
+    }
+
+    #[test]
+    fn find_excluding_self_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        bk.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+        let (words, _): (Vec<&str>, Vec<isize>) =
+            bk.find_excluding_self("boo", 1).into_iter().unzip();
+        assert_eq!(words, ["book", "boon"]);
+
+        // Distinct values at distance 0 are kept
+        let mut bk = BkTree::new(CaseInsensitive::default());
+        bk.insert_all(vec!["Book", "boon"]);
+        assert_eq!(
+            bk.find_excluding_self("book", 1),
+            [(&"Book", 0), (&"boon", 1)]
+        );
+        assert_eq!(bk.find_excluding_self("Book", 1), [(&"boon", 1)]);
+    }
+
+    #[test]
+    fn truncate_depth_test() {
+        let mut bk = BkTree::new(ManhattanDistance);
+        assert_eq!(bk.depth(), 0);
+
+        // Inserting around 0 first puts each negative value under its opposite
+        bk.insert_all(vec![[0], [1], [-1], [2], [-2], [3], [-3]]);
+        assert_eq!(bk.depth(), 2);
+
+        bk.truncate_depth(1);
+        assert_eq!(bk.depth(), 1);
+        let mut words: Vec<i64> = bk.iter().map(|[i]| *i).collect();
+        words.sort();
+        assert_eq!(words, [-3, -2, -1, 0, 1, 2, 3]);
+        for i in -3..=3 {
+            assert_eq!(bk.find([i], 0), [(&[i], 0)]);
+        }
+        assert_eq!(bk.find([0], 1).len(), 3);
+
+        // Inserting words of decreasing common suffix length builds a chain
+        let words: Vec<String> = (0..16)
+            .map(|j| format!("{}b{}", "a".repeat(15 - j), "c".repeat(j)))
+            .collect();
+        let mut bk = BkTree::new(SuffixDistance);
+        bk.insert_all(words.clone());
+        assert_eq!(bk.depth(), 15);
+        bk.truncate_depth(3);
+        assert!(bk.depth() <= 3);
+        assert_eq!(bk.len(), words.len());
+        for w in &words {
+            assert!(bk.contains_exact(w));
+        }
+
+        // Equidistant elements can only form a chain, which is kept as is
+        let mut bk = BkTree::new(HammingDistance);
+        bk.insert_all((0..12).map(|i| 1u32 << i));
+        assert_eq!(bk.depth(), 11);
+        bk.truncate_depth(3);
+        assert_eq!(bk.depth(), 11);
+        assert_eq!(bk.len(), 12);
+        for i in 0..12 {
+            assert_eq!(bk.find(1u32 << i, 0), [(&(1u32 << i), 0)]);
+        }
+    }
+
+    #[test]
+    fn canonical_dedup_test() {
+        let mut bk =
+            BkTree::with_canonical_dedup(LevenshteinDistance, |w: &String| w.to_lowercase());
+        assert!(bk.insert("Book".to_string()));
+        assert!(!bk.insert("book".to_string()));
+        // Duplicates are found whatever their distance
+        assert_eq!(bk.try_insert("BOOK".to_string()), Ok(false));
+        assert!(bk.insert("boo".to_string()));
+        assert!(bk.insert("Cake".to_string()));
+        assert!(!bk.insert("CAKE".to_string()));
+        assert_eq!(
+            bk.insert_if_absent("cAKE".to_string()),
+            Err(&"Cake".to_string())
+        );
+        assert_eq!(bk.get_or_insert("BoOk".to_string()), "Book");
+        assert_eq!(bk.len(), 3);
+
+        // Searches still use the original casing
+        assert_eq!(bk.find("book".to_string(), 0), []);
+        assert_eq!(
+            bk.find("book".to_string(), 1),
+            [(&"Book".to_string(), 1), (&"boo".to_string(), 1)]
+        );
+        assert!(!bk.clone().insert("BOO".to_string()));
+
+        // Removed elements no longer count as duplicates
+        assert_eq!(bk.remove_within(&"Book".to_string(), 0), 1);
+        assert!(bk.insert("BOOK".to_string()));
+        assert!(!bk.insert("boo".to_string()));
+        let mut cakes = bk.split_off(|w| w.starts_with(['c', 'C']));
+        assert!(bk.insert("cake".to_string()));
+        assert!(!cakes.insert("CAKE".to_string()));
+
+        // Canonicalizations can capture their environment
+        let suffix = "s".to_string();
+        let mut bk = BkTree::with_canonical_dedup(LevenshteinDistance, move |w: &String| {
+            w.strip_suffix(suffix.as_str()).unwrap_or(w).to_string()
+        });
+        bk.insert_all(["book", "books", "boo"].map(String::from));
+        assert_eq!(bk.len(), 2);
+    }
+
+    #[test]
+    fn find_sorted_by_test() {
+        let mut tree: BkTree<&str> = BkTree::new(LevenshteinDistance);
+        tree.insert_all(vec!["bar", "baz", "bat", "ba", "bart"]);
+
+        let by_length_then_reversed = |a: &&str, b: &&str| a.len().cmp(&b.len()).then(b.cmp(a));
+        let found: Vec<(&str, isize)> = tree
+            .find_sorted_by("bab", 1, by_length_then_reversed)
+            .into_iter()
+            .map(|(word, dist)| (*word, dist))
+            .collect();
+        assert_eq!(found, [("ba", 1), ("baz", 1), ("bat", 1), ("bar", 1)]);
+        assert_eq!(
+            tree.find_sorted_by("bab", 1, |a, b| a.cmp(b)),
+            tree.find_sorted("bab", 1)
+        );
+    }
+
+    #[test]
+    fn builder_test() {
+        let words = vec![
+            "Book", "book", "boo", "boon", "Cake", "CAKE", "cape", "cart",
+        ];
+        let bk = BkTreeBuilder::new(LcsDistance)
+            .distance(LevenshteinDistance)
+            .index_threshold(1)
+            .balanced(true)
+            .build_from(words.clone());
+        assert_eq!(bk.len(), words.len());
+        assert_eq!(bk.index_threshold, 1);
+        let mut plain = BkTree::new(LevenshteinDistance);
+        plain.insert_all(words.clone());
+        assert_eq!(bk.find_sorted("cak", 2), plain.find_sorted("cak", 2));
+
+        let lowercase = |w: &String| w.to_lowercase();
+        let deduped = BkTreeBuilder::new(LevenshteinDistance)
+            .dedup(lowercase)
+            .with_capacity(words.len())
+            .balanced(true)
+            .build_from(words.iter().map(|w| w.to_string()));
+        assert_eq!(deduped.len(), 6);
+        assert_eq!(deduped.find("book".to_string(), 0), []);
+        assert!(deduped.depth() <= deduped.rebuild_preview());
+
+        let multiset = BkTreeBuilder::new(LevenshteinDistance)
+            .multiset(true)
+            .balanced(true)
+            .build_from(vec!["book", "book", "boo", "book"]);
+        assert_eq!(multiset.len(), 4);
+        assert_eq!(multiset.find("book", 0).len(), 3);
+
+        let mut empty = BkTreeBuilder::new(LevenshteinDistance)
+            .multiset(true)
+            .build();
+        empty.insert_all(vec!["book", "book"]);
+        assert_eq!(empty.len(), 2);
+    }
+
+    #[test]
+    fn insert_if_absent_test() {
+        let mut tree = BkTree::new(CaseInsensitive::default());
+        assert_eq!(
+            tree.insert_if_absent("book".to_string()),
+            Ok(&"book".to_string())
+        );
+        assert_eq!(
+            tree.insert_if_absent("boo".to_string()),
+            Ok(&"boo".to_string())
+        );
+        assert_eq!(
+            tree.insert_if_absent("BOO".to_string()),
+            Err(&"boo".to_string())
+        );
+        assert_eq!(
+            tree.insert_if_absent("Book".to_string()),
+            Err(&"book".to_string())
+        );
+        assert_eq!(tree.len(), 2);
+    }
+
+    #[test]
+    fn prune_leaves_test() {
+        let mut tree: BkTree<&str> = BkTree::new(LevenshteinDistance);
+        tree.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+        // Leaves are "cape", "cart", "boon" and "cook", "cake" is the parent of the first two
+        assert_eq!(tree.prune_leaves(|w| w.starts_with('c')), 3);
+        assert_eq!(tree.len(), 5);
+        for word in ["book", "books", "boo", "boon", "cake"] {
+            assert_eq!(tree.find(word, 0), [(&word, 0)]);
+        }
+        assert!(tree.find("cape", 0).is_empty());
+        // "cake" became a leaf
+        assert_eq!(tree.prune_leaves(|w| w.starts_with('c')), 1);
+        assert_eq!(tree.prune_leaves(|_| false), 0);
+
+        let mut single = BkTree::new(HammingDistance);
+        single.insert(1);
+        assert_eq!(single.prune_leaves(|_| true), 1);
+        assert!(single.is_empty());
+    }
+
+    #[test]
+    fn distance_histogram_test() {
+        let mut bk = BkTree::new(HammingDistance);
+        assert!(bk.distance_histogram(&0).is_empty());
+        bk.insert_all(0..16u8);
+        assert_eq!(
+            bk.distance_histogram(&0).into_iter().collect::<Vec<_>>(),
+            [(0, 1), (1, 4), (2, 6), (3, 4), (4, 1)]
+        );
+        assert_eq!(
+            bk.distance_histogram(&0xff).into_iter().collect::<Vec<_>>(),
+            [(4, 1), (5, 4), (6, 6), (7, 4), (8, 1)]
+        );
+    }
+
+    #[test]
+    fn bounded_max_test() {
+        assert_eq!(Distance::<u8>::bounded_max(&HammingDistance), Some(8));
+        assert_eq!(Distance::<str>::bounded_max(&LevenshteinDistance), None);
+
+        let mut bk = BkTree::new(HammingDistance);
+        bk.insert_all(0..=255u8);
+        let full = bk.find(0b1010_1010, 8);
+        assert_eq!(full.len(), 256);
+        assert_eq!(bk.find(0b1010_1010, 100), full);
+        assert_eq!(
+            full,
+            bk.find_by(|w| HammingDistance.distance(w, &0b1010_1010), 8)
+        );
+        assert!(full
+            .iter()
+            .all(|(w, dist)| *dist == HammingDistance.distance(*w, &0b1010_1010)));
+        assert_eq!(bk.find(0b1010_1010, 7).len(), 255);
+    }
+
+    #[test]
+    fn sample_test() {
+        let mut bk = BkTree::new(HammingDistance);
+        assert!(bk.sample(3, 7).is_empty());
+        bk.insert_all(0..100u32);
+
+        let sample = bk.sample(10, 7);
+        assert_eq!(sample.len(), 10);
+        assert_eq!(sample, bk.sample(10, 7));
+        assert_ne!(sample, bk.sample(10, 8));
+        let distinct: HashSet<&u32> = sample.iter().copied().collect();
+        assert_eq!(distinct.len(), 10);
+
+        assert_eq!(bk.sample(0, 7), Vec::<&u32>::new());
+        let mut all: Vec<u32> = bk.sample(1000, 7).into_iter().copied().collect();
+        all.sort();
+        assert_eq!(all, (0..100).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn closest_pair_test() {
+        let mut tree: BkTree<&str> = BkTree::new(LevenshteinDistance);
+        assert_eq!(tree.closest_pair(), None);
+        tree.insert("book");
+        assert_eq!(tree.closest_pair(), None);
+        tree.insert_all(vec!["cake", "apple", "boot"]);
+        let (a, b, dist) = tree.closest_pair().unwrap();
+        let mut pair = [*a, *b];
+        pair.sort();
+        assert_eq!((pair, dist), (["book", "boot"], 1));
+    }
+
+    #[test]
+    fn level_widths_test() {
+        let mut tree: BkTree<&str> = BkTree::new(LevenshteinDistance);
+        assert!(tree.level_widths().is_empty());
+        tree.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+        assert_eq!(tree.level_widths(), [1, 2, 3, 2]);
+        assert_eq!(tree.level_widths().iter().sum::<usize>(), tree.len());
+        assert_eq!(tree.level_widths().len(), tree.depth() + 1);
+    }
+
+    #[test]
+    fn is_balanced_test() {
+        let mut bk = BkTree::new(HammingDistance);
+        assert!(bk.is_balanced(0.0));
+        bk.insert_all(0..256);
+        bk.rebuild();
+        assert!(bk.is_balanced(3.0));
+
+        // Elements all at distance 1 of one another can only form a chain
+        struct Discrete;
+        impl Distance<i32> for Discrete {
+            fn distance(&self, a: &i32, b: &i32) -> isize {
+                (a != b) as isize
+            }
+        }
+        let mut chain = BkTree::new(Discrete);
+        chain.insert_all(0..20);
+        assert_eq!(chain.depth(), 19);
+        assert!(!chain.is_balanced(3.0));
+        assert!(chain.is_balanced(4.5));
+    }
+
+    #[test]
+    fn rebuild_preview_test() {
+        let mut bk = BkTree::new(ManhattanDistance);
+        assert_eq!(bk.rebuild_preview(), 0);
+
+        bk.insert_all(vec![
+            [0, 0],
+            [1, 0],
+            [-1, 0],
+            [2, 0],
+            [-2, 0],
+            [3, 0],
+            [-3, 0],
+            [0, 5],
+        ]);
+        let before = bk.clone();
+        let preview = bk.rebuild_preview();
+        assert!(bk == before);
+        assert_eq!(bk.depth(), before.depth());
+
+        bk.rebuild();
+        assert_eq!(bk.depth(), preview);
+        assert!(preview < before.depth());
+        let mut words: Vec<_> = bk.iter().collect();
+        let mut expected: Vec<_> = before.iter().collect();
+        words.sort();
+        expected.sort();
+        assert_eq!(words, expected);
+    }
+
+    #[test]
+    fn for_each_match_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        bk.for_each_match("bo", 2, |_, _| panic!("empty BK-tree has no match"));
+
+        bk.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+        for (query, max_dist) in [("bo", 2), ("ca", 3), ("not here", 0)] {
+            let mut found = Vec::new();
+            bk.for_each_match(query, max_dist, |word, dist| found.push((*word, dist)));
+            let expected: Vec<_> = bk
+                .find(query, max_dist)
+                .into_iter()
+                .map(|(word, dist)| (*word, dist))
+                .collect();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn trim_to_radius_test() {
+        let mut bk = BkTree::new(ManhattanDistance);
+        assert_eq!(bk.trim_to_radius(&[0, 0], 5), 0);
+        bk.insert_all(vec![
+            [0, 0],
+            [1, 1],
+            [3, 0],
+            [5, 5],
+            [-1, 2],
+            [10, 0],
+            [0, -6],
+        ]);
+
+        assert_eq!(bk.trim_to_radius(&[1, 0], 4), 3);
+        let mut kept: Vec<[i64; 2]> = bk.iter().copied().collect();
+        kept.sort();
+        assert_eq!(kept, [[-1, 2], [0, 0], [1, 1], [3, 0]]);
+        for point in &kept {
+            assert_eq!(bk.find(*point, 0), [(point, 0)]);
+        }
+
+        assert_eq!(bk.trim_to_radius(&[100, 100], 1), 4);
+        assert!(bk.is_empty());
+    }
+
+    #[test]
+    fn keep_nearest_test() {
+        let words = vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ];
+        for k in [0, 3, 8, 20] {
+            let mut bk = BkTree::new(LevenshteinDistance);
+            bk.insert_all(words.clone());
+            bk.keep_nearest(&"bo", k);
+            assert_eq!(bk.len(), k.min(words.len()));
+
+            let mut kept: Vec<&str> = bk.iter().copied().collect();
+            kept.sort();
+            let mut by_dist = words.clone();
+            by_dist.sort_by_key(|w| LevenshteinDistance.distance(*w, "bo"));
+            let cutoff = by_dist[..k.min(words.len())]
+                .iter()
+                .map(|w| LevenshteinDistance.distance(*w, "bo"))
+                .max();
+            assert!(kept.iter().all(
+                |w| words.contains(w) && Some(LevenshteinDistance.distance(*w, "bo")) <= cutoff
+            ));
+        }
+
+        let mut bk = BkTree::new(LevenshteinDistance);
+        bk.insert_all(words);
+        bk.keep_nearest(&"bo", 3);
+        let mut kept: Vec<&str> = bk.iter().copied().collect();
+        kept.sort();
+        assert_eq!(kept, ["boo", "book", "boon"]);
+        assert_eq!(bk.find("boon", 0), [(&"boon", 0)]);
+
+        // 1 and 14 are both at distance 2 of 8, 1 is a child of the root and 14 a grandchild
+        let mut bk = BkTree::new(HammingDistance);
+        bk.insert_all(vec![0, 1, 3, 7, 6, 14]);
+        assert_eq!(bk.children_words_of(&7), Some(vec![(&14, 2)]));
+        bk.keep_nearest(&8, 2);
+        let mut kept: Vec<i32> = bk.iter().copied().collect();
+        kept.sort();
+        assert_eq!(kept, [0, 1]);
+    }
+
+    #[test]
+    fn into_bfs_test() {
+        let mut bk = BkTree::new(HammingDistance);
+        assert_eq!(bk.clone().into_bfs().count(), 0);
+
+        bk.insert_all(vec![0, 4, 5, 14, 15, 1]);
+        let linear: Vec<(i32, Option<isize>)> = bk.clone().into_bfs().collect();
+        assert_eq!(
+            linear,
+            [
+                (0, None),
+                (4, Some(1)),
+                (5, Some(2)),
+                (14, Some(3)),
+                (15, Some(4)),
+                (1, Some(2))
+            ]
+        );
+
+        let mut rebuilt = BkTree::new(HammingDistance);
+        rebuilt.insert_all(linear.iter().map(|(word, _)| *word));
+        assert!(rebuilt == bk);
+        assert_eq!(rebuilt.into_bfs().collect::<Vec<_>>(), linear);
+    }
+
+    #[test]
+    fn contains_exact_test() {
+        let mut bk = BkTree::new(CaseInsensitive::default());
+        assert!(!bk.contains_exact(&"book"));
+        bk.insert_all(vec!["Book", "boo", "Cake", "cape"]);
+
+        assert!(bk.contains_within(&"book", 0));
+        assert!(!bk.contains_exact(&"book"));
+        assert!(bk.contains_exact(&"Book"));
+        assert!(bk.contains_within(&"CAPE", 0));
+        assert!(!bk.contains_exact(&"CAPE"));
+        assert!(bk.contains_exact(&"cape"));
+        assert!(!bk.contains_within(&"cart", 0));
+        assert!(!bk.contains_exact(&"cart"));
+
+        // Duplicates of a multiset BK-tree are stored below the first one
+        let mut bk = BkTree::with_multiset(CaseInsensitive::default(), true);
+        bk.insert_all(vec!["Book", "boo", "book", "BOOK"]);
+        assert!(bk.contains_exact(&"book"));
+        assert!(bk.contains_exact(&"BOOK"));
+        assert!(!bk.contains_exact(&"bOOk"));
+        assert_eq!(bk.children_of(&"book"), Some(1));
+        assert_eq!(bk.children_words_of(&"BooK"), Some(vec![(&"boo", 1)]));
+    }
+
+    #[test]
+    fn find_missing_test() {
+        let expected = vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ];
+        let mut bk = BkTree::new(LevenshteinDistance);
+        assert!(bk.contains_all(&[]));
+        assert_eq!(
+            bk.find_missing(&expected),
+            expected.iter().collect::<Vec<_>>()
+        );
+
+        bk.insert_all(vec!["book", "boo", "cake", "cart", "carts"]);
+        assert!(!bk.contains_all(&expected));
+        assert_eq!(
+            bk.find_missing(&expected),
+            [&"books", &"boon", &"cook", &"cape"]
+        );
+
+        bk.insert_all(vec!["books", "boon", "cook", "cape"]);
+        assert!(bk.contains_all(&expected));
+        assert!(bk.find_missing(&expected).is_empty());
+    }
+
+    #[test]
+    fn edge_gcd_test() {
+        let mut bk = BkTree::new(LcsDistance);
+        assert_eq!(bk.min_positive_edge(), None);
+        assert_eq!(bk.gcd_of_edges(), None);
+
+        bk.insert_all(vec!["book", "boon", "cook", "cake", "cape", "cart"]);
+        assert_eq!(bk.min_positive_edge(), Some(2));
+        assert_eq!(bk.gcd_of_edges(), Some(2));
+
+        bk.insert("books");
+        assert_eq!(bk.min_positive_edge(), Some(1));
+        assert_eq!(bk.gcd_of_edges(), Some(1));
+    }
+
+    #[test]
+    fn into_sorted_by_distance_test() {
+        let mut bk = BkTree::new(HammingDistance);
+        bk.insert_all(vec![0, 4, 5, 14, 15]);
+
+        let sorted = bk.into_sorted_by_distance(&13);
+        assert!(sorted.windows(2).all(|w| w[0].1 <= w[1].1));
+        let mut words: Vec<i32> = sorted.iter().map(|(w, _)| *w).collect();
+        words.sort();
+        assert_eq!(words, [0, 4, 5, 14, 15]);
+        assert_eq!(sorted[sorted.len() - 1], (0, 3));
+    }
+
+    #[test]
+    fn find_farthest_within_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        assert_eq!(bk.find_farthest_within("bo", 2), None);
+
+        bk.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+        assert_eq!(bk.find_farthest_within("boo", 2), Some((&"books", 2)));
+        assert_eq!(bk.find_farthest_within("bo", 1), Some((&"boo", 1)));
+        assert_eq!(bk.find_farthest_within("zzzz", 2), None);
+    }
+
+    #[test]
+    fn map_into_test() {
+        let mut bk = BkTree::new(HammingDistance);
+        bk.insert_all(vec![10, 11, 12, 100, 1000]);
+
+        let mapped = bk.map_into(|i| i.to_string(), LevenshteinDistance);
+        assert_eq!(mapped.len(), 5);
+        let mut words: Vec<&str> = mapped
+            .find_borrowed("10", 1)
+            .into_iter()
+            .map(|(w, _)| w.as_str())
+            .collect();
+        words.sort();
+        assert_eq!(words, ["10", "100", "11", "12"]);
+    }
+
+    #[test]
+    fn find_with_ratio_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        bk.insert_all(vec!["book", "boon", "boot", "cook", "cake"]);
+
+        let mut found = bk.find_with_ratio("book", 4);
+        found.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        assert_eq!(found[0], (&"book", 1.0));
+        assert_eq!(found[1].1, 0.75);
+        assert_eq!(found[found.len() - 1], (&"cake", 0.0));
+        assert!(found.windows(2).all(|w| w[0].1 >= w[1].1));
+
+        let mut bk = BkTree::new(HammingDistance);
+        bk.insert_all(vec![0u8, 0xff]);
+        assert_eq!(bk.find_with_ratio(0x0f, 8), [(&0, 0.5), (&0xff, 0.5)]);
+    }
+
+    #[test]
+    fn clone_from_test() {
+        let mut source = BkTree::new(LevenshteinDistance);
+        source.insert_all(vec!["book", "books", "boo", "boon", "cook"]);
+
+        let mut dest = BkTree::new(LevenshteinDistance);
+        dest.insert_all(vec!["cake", "cape", "cart"]);
+        dest.clone_from(&source);
+        assert!(dest == source);
+
+        let mut empty = BkTree::new(LevenshteinDistance);
+        empty.clone_from(&source);
+        assert!(empty == source);
+        assert!(source.clone() == source);
+
+        // Children of the nodes below the root keep their buffers
+        let mut dest = source.clone();
+        dest.insert("bookss");
+        let books = &mut dest.root.as_mut().unwrap().children[0].1;
+        assert_eq!(books.word, "books");
+        books.children.reserve(64);
+        dest.clone_from(&source);
+        assert!(dest == source);
+        assert!(
+            dest.root.as_ref().unwrap().children[0]
+                .1
+                .children
+                .capacity()
+                >= 64
+        );
+    }
+
+    #[test]
+    fn composite_distance_test() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Product {
+            name: &'static str,
+            code: u8,
+        }
+
+        impl CompositeKey for Product {
+            type First = str;
+            type Second = u8;
+
+            fn first(&self) -> &str {
+                self.name
+            }
+
+            fn second(&self) -> &u8 {
+                &self.code
+            }
+        }
+
+        let a = Product {
+            name: "book",
+            code: 0b0000,
+        };
+        let b = Product {
+            name: "boon",
+            code: 0b0111,
+        };
+        let sum = SumDistance(LevenshteinDistance, HammingDistance);
+        let max = MaxDistance(LevenshteinDistance, HammingDistance);
+        assert_eq!(sum.distance(&a, &b), 4);
+        assert_eq!(max.distance(&a, &b), 3);
+        assert_eq!(sum.distance(&("cake", 1u8), &("cape", 3u8)), 2);
+
+        let products = vec![
+            a.clone(),
+            b.clone(),
+            Product {
+                name: "books",
+                code: 0b0001,
+            },
+            Product {
+                name: "cake",
+                code: 0b0000,
+            },
+        ];
+        let mut bk = BkTree::new(sum);
+        bk.insert_all(products.clone());
+        let names: Vec<_> = bk
+            .find_borrowed(&a, 2)
+            .into_iter()
+            .map(|(p, d)| (p.name, d))
+            .collect();
+        assert_eq!(names, [("book", 0), ("books", 2)]);
+
+        let mut bk = BkTree::new(max);
+        bk.insert_all(products);
+        let mut names: Vec<_> = bk
+            .find_borrowed(&a, 3)
+            .into_iter()
+            .map(|(p, d)| (p.name, d))
+            .collect();
+        names.sort();
+        assert_eq!(names, [("book", 0), ("books", 1), ("boon", 3)]);
+    }
+
+    #[test]
+    fn distance_mut_test() {
+        // Levenshtein distance caching its results and counting cache hits
+        struct Caching {
+            cache: HashMap<(String, String), isize>,
+            hits: usize,
+        }
+        impl DistanceMut<String> for Caching {
+            fn distance(&mut self, a: &String, b: &String) -> isize {
+                let key = if a <= b {
+                    (a.clone(), b.clone())
+                } else {
+                    (b.clone(), a.clone())
+                };
+                if let Some(dist) = self.cache.get(&key) {
+                    self.hits += 1;
+                    return *dist;
+                }
+                let dist = Distance::distance(&LevenshteinDistance, a, b);
+                self.cache.insert(key, dist);
+                dist
+            }
+        }
+
+        let words: Vec<String> = vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        let mut bk = BkTreeMut::new(Caching {
+            cache: HashMap::new(),
+            hits: 0,
+        });
+        bk.insert_all(words.clone());
+        assert_eq!(bk.len(), 8);
+
+        let mut reference = BkTree::new(LevenshteinDistance);
+        reference.insert_all(words);
+        let expected = reference.find("bo".to_string(), 2);
+
+        let hits = bk.dist().hits;
+        assert_eq!(bk.find("bo".to_string(), 2), expected);
+        assert_eq!(bk.dist().hits, hits);
+        assert_eq!(bk.find("bo".to_string(), 2), expected);
+        assert!(bk.dist().hits > hits);
+
+        // Immutable metrics are mutable metrics too
+        let mut bk = BkTreeMut::new(HammingDistance);
+        bk.insert_all(vec![0, 4, 5, 14, 15]);
+        assert_eq!(bk.find(13, 1), [(&5, 1), (&15, 1)]);
+    }
+
+    #[test]
+    fn insertion_paths_test() {
+        let words: Vec<u32> = (0..300u32).map(|i| (i * 37) % 256).collect();
+        for (multiset, threshold) in [
+            (false, crate::DEFAULT_INDEX_THRESHOLD),
+            (true, 2),
+            (false, 0),
+        ] {
+            let new_tree = || {
+                let mut bk = BkTree::with_multiset(HammingDistance, multiset);
+                bk.index_threshold = threshold;
+                bk
+            };
+            let mut inserted = new_tree();
+            let mut tried = new_tree();
+            let mut absent = new_tree();
+            let mut hinted = new_tree();
+            let mut mutable = BkTreeMut::new(HammingDistance);
+            let mut seen = HashSet::new();
+            for word in &words {
+                let new = inserted.insert(*word);
+                assert_eq!(mutable.insert(*word), !seen.contains(word));
+                assert_eq!(tried.try_insert(*word), Ok(new));
+                assert_eq!(absent.insert_if_absent(*word).is_ok(), seen.insert(*word));
+                let hint = hinted
+                    .root
+                    .as_ref()
+                    .map_or(0, |root| HammingDistance.distance(&root.word, word));
+                assert_eq!(hinted.insert_with_root_distance(*word, hint), new);
+            }
+            assert_eq!(inserted.len(), if multiset { 300 } else { 256 });
+            assert!(tried == inserted);
+            assert!(hinted == inserted);
+            assert_eq!(absent.len(), 256);
+            assert_eq!(mutable.len(), 256);
+            for query in [0, 17, 255] {
+                assert_eq!(inserted.find(query, 2).len(), tried.find(query, 2).len());
+                assert_eq!(mutable.find(query, 2), absent.find(query, 2));
+            }
+        }
+    }
+
+    #[test]
+    fn insert_with_root_distance_test() {
+        let words = vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ];
+        let mut tree: BkTree<&str> = BkTree::new(LevenshteinDistance);
+        tree.insert_all(words.clone());
+
+        let mut hinted: BkTree<&str> = BkTree::new(LevenshteinDistance);
+        for word in words {
+            let hint = hinted
+                .root
+                .as_ref()
+                .map_or(0, |root| LevenshteinDistance.distance(root.word, word));
+            assert!(hinted.insert_with_root_distance(word, hint));
+        }
+        assert!(!hinted.insert_with_root_distance("boo", 1));
+        assert!(hinted == tree);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn insert_with_wrong_root_distance_test() {
+        let mut tree: BkTree<&str> = BkTree::new(LevenshteinDistance);
+        tree.insert("book");
+        tree.insert_with_root_distance("cake", 1);
+    }
+
+    #[test]
+    fn find_grouped_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        assert!(bk.find_grouped("book", 2).is_empty());
+        bk.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+        let groups: Vec<(isize, Vec<&str>)> = bk
+            .find_grouped("boo", 2)
+            .into_iter()
+            .map(|(dist, words)| (dist, words.into_iter().copied().collect()))
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                (0, vec!["boo"]),
+                (1, vec!["book", "boon"]),
+                (2, vec!["books", "cook"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn find_filtered_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        bk.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+        let banned = ["boo", "cook"];
+        let checked = std::cell::Cell::new(0);
+        let found = bk.find_filtered("book", 1, |word| {
+            checked.set(checked.get() + 1);
+            !banned.contains(word)
+        });
+        assert_eq!(found, [(&"book", 0), (&"books", 1), (&"boon", 1)]);
+        assert_eq!(checked.get(), bk.find("book", 1).len());
+        assert_eq!(bk.find_filtered("book", 1, |_| true), bk.find("book", 1));
+    }
+
+    #[test]
+    fn find_cow_test() {
+        let mut tree: BkTree<String> = BkTree::new(LevenshteinDistance);
+        tree.insert_all(["book", "books", "boo", "cake"].map(String::from));
+
+        let found = tree.find_cow("book".to_string(), 1);
+        assert!(found
+            .iter()
+            .all(|(word, _)| matches!(word, Cow::Borrowed(_))));
+        let owned: Vec<String> = found
+            .into_iter()
+            .filter(|(_, distance)| *distance == 1)
+            .map(|(word, _)| word.into_owned())
+            .collect();
+        drop(tree);
+        assert_eq!(owned, ["books", "boo"]);
+    }
+
+    #[test]
+    fn find_memory_bounded_test() {
+        let mut tree: BkTree<&str> = BkTree::new(LevenshteinDistance);
+        tree.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+        let pair_size = std::mem::size_of::<(&&str, isize)>();
+
+        let (found, truncated) = tree.find_memory_bounded("book", 4, 3 * pair_size + 1);
+        assert!(truncated);
+        assert_eq!(found.len(), 3);
+        assert!(found.len() * pair_size <= 3 * pair_size + 1);
+        assert_eq!(found, tree.find("book", 4)[..3]);
+
+        let all = tree.find("book", 1);
+        let (found, truncated) = tree.find_memory_bounded("book", 1, all.len() * pair_size);
+        assert!(!truncated);
+        assert_eq!(found, all);
+
+        let (found, truncated) = tree.find_memory_bounded("book", 1, 0);
+        assert!(truncated);
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn deref_distance_test() {
+        // Evaluated on the `str` targets of the elements
+        let words = vec!["Book", "boo", "CAKE", "cape"];
+        let mut borrowed: BkTree<&str, _> = BkTree::new(DerefDistance(CaseInsensitive::default()));
+        borrowed.insert_all(words.clone());
+        let mut owned: BkTree<String, _> = BkTree::new(DerefDistance(CaseInsensitive::default()));
+        owned.insert_all(words.iter().map(|w| w.to_string()));
+
+        assert!(!borrowed.insert("BOOK"));
+        assert!(!owned.insert("cAkE".to_string()));
+        assert_eq!(borrowed.find("book", 1), [(&"Book", 0), (&"boo", 1)]);
+        let found: Vec<(&str, isize)> = owned
+            .find("book".to_string(), 1)
+            .into_iter()
+            .map(|(w, d)| (w.as_str(), d))
+            .collect();
+        assert_eq!(found, [("Book", 0), ("boo", 1)]);
+
+        let boxed = DerefDistance(HammingDistance);
+        assert_eq!(boxed.distance(&Box::new(0b101u8), &Box::new(0b110u8)), 2);
+        assert_eq!(Distance::<Box<u8>>::bounded_max(&boxed), Some(8));
+    }
+
+    #[test]
+    fn nullable_distance_test() {
+        let dist = NullableDistance {
+            inner: LevenshteinDistance,
+            penalty: 3,
+        };
+        let mut tree: BkTree<Option<&str>, _> = BkTree::new(dist);
+        tree.insert_all(vec![
+            Some("book"),
+            None,
+            Some("boo"),
+            Some("cake"),
+            None,
+            Some("cook"),
+        ]);
+        assert_eq!(tree.len(), 5);
+
+        assert_eq!(tree.find(None, 0), [(&None, 0)]);
+        let mut found = tree.find(Some("boo"), 3);
+        found.sort();
+        assert_eq!(
+            found,
+            [
+                (&None, 3),
+                (&Some("boo"), 0),
+                (&Some("book"), 1),
+                (&Some("cook"), 2),
+            ]
+        );
+        assert_eq!(tree.find(None, 2), [(&None, 0)]);
+    }
+
+    #[test]
+    fn suffix_distance_test() {
+        assert_eq!(SuffixDistance.distance("tion", "nation"), 2);
+        assert_eq!(SuffixDistance.distance("nation", "station"), 2);
+        assert_eq!(SuffixDistance.distance("tion", "tiona"), 5);
+        assert_eq!(SuffixDistance.distance("héllo", "hello"), 2);
+        assert_eq!(SuffixDistance.distance("", "abc"), 3);
+        assert_eq!(SuffixDistance.distance("abc", "abc"), 0);
+
+        let words = vec!["nation", "station", "motion", "tiona", "lotions", "notice"];
+        let mut tree = BkTree::new(SuffixDistance);
+        tree.insert_all(words.clone());
+        let mut found: Vec<&str> = tree.find("tion", 2).into_iter().map(|(w, _)| *w).collect();
+        found.sort();
+        assert_eq!(found, ["motion", "nation"]);
+
+        // Levenshtein distance also matches words with "tion" elsewhere
+        let mut tree = BkTree::new(LevenshteinDistance);
+        tree.insert_all(words);
+        let mut found: Vec<&str> = tree.find("tion", 2).into_iter().map(|(w, _)| *w).collect();
+        found.sort();
+        assert_eq!(found, ["motion", "nation", "tiona"]);
+    }
+
+    #[test]
+    fn metaphone_distance_test() {
+        assert_eq!(MetaphoneDistance::encode("Smith"), "SM0");
+        assert_eq!(MetaphoneDistance::encode("Knight"), "NT");
+        assert_eq!(MetaphoneDistance::encode("Philips"), "FLPS");
+        assert_eq!(MetaphoneDistance::encode("Xavier"), "SFR");
+        assert_eq!(MetaphoneDistance.distance("Smith", "Smyth"), 0);
+        assert_eq!(MetaphoneDistance.distance("Catherine", "Kathryn"), 0);
+        assert!(MetaphoneDistance.distance("Smith", "Jones") > 2);
+
+        let mut tree = BkTree::with_multiset(MetaphoneDistance, true);
+        tree.insert_all(vec!["Smith", "Smyth", "Schmidt", "Jones", "Johns"]);
+        let mut found: Vec<&str> = tree
+            .find("Smithe", 0)
+            .into_iter()
+            .map(|(w, _)| *w)
+            .collect();
+        found.sort();
+        assert_eq!(found, ["Smith", "Smyth"]);
+    }
+
+    #[test]
+    fn alignment_test() {
+        fn apply(a: &str, ops: &[EditOp]) -> String {
+            let mut chars = a.chars();
+            let mut out = String::new();
+            for op in ops {
+                match *op {
+                    EditOp::Keep(c) => {
+                        assert_eq!(chars.next(), Some(c));
+                        out.push(c);
+                    }
+                    EditOp::Substitute(from, to) => {
+                        assert_eq!(chars.next(), Some(from));
+                        out.push(to);
+                    }
+                    EditOp::Insert(c) => out.push(c),
+                    EditOp::Delete(c) => assert_eq!(chars.next(), Some(c)),
+                }
+            }
+            assert_eq!(chars.next(), None);
+            out
+        }
+
+        for (a, b) in [
+            ("kitten", "sitting"),
+            ("book", "cake"),
+            ("", "boo"),
+            ("boo", ""),
+            ("flaw", "lawn"),
+            ("héllo", "hello"),
+            ("same", "same"),
+        ] {
+            let ops = LevenshteinDistance.alignment(a, b);
+            assert_eq!(apply(a, &ops), b);
+            let edits = ops
+                .iter()
+                .filter(|op| !matches!(op, EditOp::Keep(_)))
+                .count();
+            assert_eq!(edits as isize, LevenshteinDistance.distance(a, b));
+        }
+    }
+
+    #[test]
+    fn keyboard_levenshtein_test() {
+        let dist = KeyboardLevenshtein::default();
+        assert!(dist.distance("cat", "cay") < dist.distance("cat", "cam"));
+        assert_eq!(dist.distance("cat", "cay"), 1);
+        assert_eq!(dist.distance("cat", "cam"), 2);
+        assert_eq!(dist.distance("Cat", "caY"), 3);
+        assert_eq!(dist.distance("", "cat"), 6);
+        for (a, b) in [("book", "boon"), ("kitten", "sitting"), ("cake", "cape")] {
+            assert_eq!(dist.distance(a, b), dist.distance(b, a));
+            assert!(dist.distance(a, b) <= 2 * LevenshteinDistance.distance(a, b));
+        }
+
+        let mut bk = BkTree::new(dist);
+        bk.insert_all(vec!["cat", "cay", "cam", "car", "cart"]);
+        let mut found = bk.find("cat", 1);
+        found.sort();
+        assert_eq!(found, [(&"car", 1), (&"cat", 0), (&"cay", 1)]);
+
+        let custom = KeyboardLevenshtein::new(vec![('a', 'b')]);
+        assert_eq!(custom.distance("a", "B"), 1);
+        assert_eq!(custom.distance("b", "a"), 1);
+        assert_eq!(custom.distance("a", "c"), 2);
+    }
+
+    #[test]
+    fn find_slice_test() {
+        let mut bk: BkTree<Vec<u8>, _> = BkTree::new(ByteLevenshteinDistance);
+        bk.insert_all(vec![
+            b"book".to_vec(),
+            b"books".to_vec(),
+            b"boo".to_vec(),
+            b"boon".to_vec(),
+            b"cook".to_vec(),
+            vec![0xff, 0xfe],
+        ]);
+
+        let (words, dists): (Vec<&Vec<u8>>, Vec<isize>) =
+            bk.find_slice(b"bo", 2).into_iter().unzip();
+        assert_eq!(words, [&b"book"[..], &[0xff, 0xfe], b"boo", b"boon"]);
+        assert_eq!(dists, [2, 2, 1, 2]);
+        assert_eq!(
+            bk.find_slice(&[0xff, 0xfe, 0xfd], 1),
+            [(&vec![0xff, 0xfe], 1)]
+        );
+        assert!(bk.find_slice(b"not here", 0).is_empty());
+
+        // Multibyte characters count as several bytes
+        assert_eq!(ByteLevenshteinDistance.distance("é", "e"), 2);
+        assert_eq!(LevenshteinDistance.distance("é", "e"), 1);
+    }
+
+    #[test]
+    fn streaming_distance_test() {
+        // Hamming distance between strings padded to the same length, computed in a single pass
+        #[derive(Clone)]
+        struct PaddedHamming;
+
+        impl StreamingDistance<char> for PaddedHamming {
+            fn distance_streams<A, B>(&self, mut a: A, mut b: B) -> isize
+            where
+                A: Iterator<Item = char> + Clone,
+                B: Iterator<Item = char> + Clone,
+            {
+                let mut dist = 0;
+                loop {
+                    match (a.next(), b.next()) {
+                        (None, None) => return dist,
+                        (x, y) if x != y => dist += 1,
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        struct MaterializedPaddedHamming;
+
+        impl Distance<&str> for MaterializedPaddedHamming {
+            fn distance(&self, a: &&str, b: &&str) -> isize {
+                let a: Vec<char> = a.chars().collect();
+                let b: Vec<char> = b.chars().collect();
+                (0..a.len().max(b.len()))
+                    .filter(|&i| a.get(i) != b.get(i))
+                    .count() as isize
+            }
+        }
+
+        let words = vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ];
+        let mut streamed = BkTree::new(Streamed(PaddedHamming));
+        streamed.insert_all(words.clone());
+        let mut materialized = BkTree::new(MaterializedPaddedHamming);
+        materialized.insert_all(words.clone());
+        for query in ["bo", "cook", "carts"] {
+            let mut a = streamed.find_stream(query.chars(), 2);
+            let mut b = materialized.find(query, 2);
+            a.sort();
+            b.sort();
+            assert!(!a.is_empty());
+            assert_eq!(a, b);
+        }
+
+        let lev = Materialized(LevenshteinDistance);
+        assert_eq!(lev.distance_streams("book".chars(), "cake".chars()), 4);
+        let mut bk = BkTree::new(lev);
+        bk.insert_all(words);
+        assert_eq!(bk.find_stream("bo".chars(), 2), bk.find("bo", 2));
+    }
+
+    #[cfg(feature = "pool")]
+    #[test]
+    fn find_with_pool_test() {
+        use crate::pool::*;
+
+        let mut bk = BkTree::new(LevenshteinDistance);
+        bk.insert_all(vec![
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]);
+        for threads in [0, 1, 3, 16] {
+            let pool = ScopedThreads(threads);
+            for (query, radius) in [("bo", 2), ("cape", 0), ("zzzz", 1), ("book", 4)] {
+                assert_eq!(
+                    bk.find_with_pool(query, radius, &pool),
+                    bk.find(query, radius)
+                );
+            }
+        }
+        let empty: BkTree<&str> = BkTree::new(LevenshteinDistance);
+        assert!(empty.find_with_pool("bo", 2, &ScopedThreads(2)).is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn sync_bktree_test() {
+        use crate::sync::*;
+        use std::sync::Arc;
+        use std::thread;
+
+        let bk = Arc::new(SyncBkTree::new(HammingDistance));
+        bk.insert(0u32);
+
+        let writers: Vec<_> = (0..4u32)
+            .map(|t| {
+                let bk = Arc::clone(&bk);
+                thread::spawn(move || {
+                    for i in 0..50 {
+                        bk.insert(1 + t * 50 + i);
+                    }
+                })
+            })
+            .collect();
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let bk = Arc::clone(&bk);
+                thread::spawn(move || {
+                    for _ in 0..50 {
+                        let found = bk.find(0, 32);
+                        assert!(found.contains(&(0, 0)));
+                        assert!(found.len() <= 201);
+                    }
+                })
+            })
+            .collect();
+        for handle in writers.into_iter().chain(readers) {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(bk.len(), 201);
+        assert_eq!(bk.find(3, 0), [(3, 0)]);
+        let tree = Arc::try_unwrap(bk).ok().unwrap().into_inner();
+        assert_eq!(tree.len(), 201);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn write_find_csv_test() {
+        let mut bk = BkTree::new(LevenshteinDistance);
+        bk.insert_all(vec!["book", "boo", "boon", "b,o\"o"]);
+        let mut out = Vec::new();
+        bk.write_find_csv("boo", 2, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "word,distance\nboo,0\nbook,1\nboon,1\n\"b,o\"\"o\",2\n"
+        );
+
+        let mut out = Vec::new();
+        bk.write_find_csv("not here", 0, &mut out).unwrap();
+        assert_eq!(out, b"word,distance\n");
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_random_datasets() {
+        use crate::test_util::*;
+
+        let words = random_words(42, 100, 3..8);
+        assert_eq!(words, random_words(42, 100, 3..8));
+        assert_ne!(words, random_words(43, 100, 3..8));
+        assert_eq!(words.len(), 100);
+        assert!(words
+            .iter()
+            .all(|w| (3..8).contains(&w.len()) && w.chars().all(|c| c.is_ascii_lowercase())));
+
+        let numbers = random_u64s(42, 100);
+        assert_eq!(numbers, random_u64s(42, 100));
+        assert_eq!(numbers.len(), 100);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn recall_vs_exact_test() {
+        use crate::test_util::*;
+
+        let mut bk = BkTree::new(LevenshteinDistance);
+        bk.insert_all(random_words(7, 2000, 3..8));
+        let queries = random_words(8, 20, 3..8);
+        let mut total = 0.0;
+        for query in &queries {
+            assert_eq!(bk.recall_vs_exact(query.clone(), 2, 0), 1.0);
+            let recall = bk.recall_vs_exact(query.clone(), 2, 1);
+            assert!((0.0..=1.0).contains(&recall));
+            total += recall;
+        }
+        assert!(total < queries.len() as f64);
+        assert_eq!(
+            bk.recall_vs_exact("zzzzzzzzzzzzzzzz".to_string(), 1, 1),
+            1.0
+        );
+    }
+
+    #[cfg(feature = "rkyv-support")]
+    #[test]
+    fn test_archive() {
+        let mut bk: BkTree<String> = BkTree::new(LevenshteinDistance);
+        bk.insert_all(
+            vec![
+                "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+            ]
+            .into_iter()
+            .map(String::from),
+        );
+
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&bk).unwrap();
+        let archived = rkyv::access::<
+            crate::ArchivedBkTree<String, LevenshteinDistance>,
+            rkyv::rancor::Error,
+        >(&bytes)
+        .unwrap();
+
+        let (words, dists): (Vec<&str>, Vec<isize>) = archived
+            .find(&LevenshteinDistance, "bo", 2)
+            .into_iter()
+            .map(|(w, d)| (w.as_str(), d))
+            .unzip();
+        assert_eq!(words, ["book", "boo", "boon"]);
+        assert_eq!(dists, [2, 1, 2]);
+
+        let decoded: BkTree<String> =
+            rkyv::deserialize::<_, rkyv::rancor::Error>(archived).unwrap();
+        assert!(decoded == bk);
+    }
+
+    #[cfg(feature = "serde-support")]
+    #[test]
+    fn test_compact_serialization() {
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Compact(#[serde(with = "crate::compact")] BkTree<String>);
+
+        // Repeated elements are all stored in a multiset BK-tree
+        let mut bk = BkTree::with_multiset(LevenshteinDistance, true);
+        for _ in 0..10 {
+            bk.insert_all(
+                vec!["a rather long word", "another rather long word"]
+                    .into_iter()
+                    .map(String::from),
+            );
+        }
+        assert_eq!(bk.len(), 20);
+
+        let encoded = bincode::serialize(&bk).unwrap();
+        let compact = Compact(bk);
+        let encoded_compact = bincode::serialize(&compact).unwrap();
+        assert!(encoded_compact.len() < encoded.len());
+
+        let mut decoded: Compact = bincode::deserialize(&encoded_compact).unwrap();
+        assert!(decoded.0 == compact.0);
+        assert_eq!(
+            decoded.0.find("a rather long word".to_string(), 1),
+            compact.0.find("a rather long word".to_string(), 1)
+        );
+        assert!(decoded.0.insert("a rather long word".to_string()));
+        assert_eq!(decoded.0.len(), 21);
+
+        let empty = Compact(BkTree::new(LevenshteinDistance));
+        let decoded: Compact = bincode::deserialize(&bincode::serialize(&empty).unwrap()).unwrap();
+        assert!(decoded.0.is_empty());
+    }
+
+    #[cfg(feature = "serde-support")]
+    #[test]
+    fn test_delta_serialization() {
+        let mut bk: BkTree<String> = BkTree::new(LevenshteinDistance);
+        bk.insert_all(
+            vec!["book", "books", "boo", "boon", "cook", "cake", "cape"]
+                .into_iter()
+                .map(String::from),
+        );
+        let mut snapshot: Vec<u8> = bincode::serialize(&bk).unwrap();
+        bk.mark_clean();
+
+        let apply = |snapshot: &[u8], bk: &BkTree<String>| {
+            let mut delta = Vec::new();
+            bk.serialize_delta(&mut bincode::Serializer::new(
+                &mut delta,
+                bincode::DefaultOptions::new(),
+            ))
+            .unwrap();
+            let mut patched: BkTree<String> = bincode::deserialize(snapshot).unwrap();
+            patched
+                .apply_delta(&mut bincode::Deserializer::from_slice(
+                    &delta,
+                    bincode::DefaultOptions::new(),
+                ))
+                .unwrap();
+            assert_eq!(
+                bincode::serialize(&patched).unwrap(),
+                bincode::serialize(bk).unwrap()
+            );
+            delta.len()
+        };
+
+        assert!(apply(&snapshot, &bk) < snapshot.len());
+
+        bk.insert("cart".to_string());
+        bk.insert("bake".to_string());
+        let delta_len = apply(&snapshot, &bk);
+        assert!(delta_len < bincode::serialize(&bk).unwrap().len());
+
+        bk.remove_within(&"cake".to_string(), 1);
+        apply(&snapshot, &bk);
+
+        snapshot = bincode::serialize(&bk).unwrap();
+        bk.mark_clean();
+        bk.remove_within(&"book".to_string(), 0);
+        apply(&snapshot, &bk);
+    }
+
+    #[cfg(feature = "serde-support")]
+    #[test]
+    fn bfs_streaming_test() {
+        let mut bk = BkTree::new(HammingDistance);
+        bk.insert_all((0..5000u32).map(|i| i.wrapping_mul(2654435761)));
+
+        let mut encoded = Vec::new();
+        crate::bfs::serialize(
+            &bk,
+            &mut bincode::Serializer::new(&mut encoded, bincode::DefaultOptions::new()),
+        )
+        .unwrap();
+        let decoded = crate::bfs::deserialize_streaming(
+            HammingDistance,
+            &mut bincode::Deserializer::with_reader(&encoded[..], bincode::DefaultOptions::new()),
+        )
+        .unwrap();
+        assert_eq!(decoded.len(), 5000);
+        assert!(decoded == bk);
+
+        let empty: BkTree<u32, _> = BkTree::new(HammingDistance);
+        let mut encoded = Vec::new();
+        crate::bfs::serialize(
+            &empty,
+            &mut bincode::Serializer::new(&mut encoded, bincode::DefaultOptions::new()),
+        )
+        .unwrap();
+        let decoded: BkTree<u32, _> = crate::bfs::deserialize_streaming(
+            HammingDistance,
+            &mut bincode::Deserializer::from_slice(&encoded, bincode::DefaultOptions::new()),
+        )
+        .unwrap();
+        assert!(decoded.is_empty());
+    }
+
+    #[cfg(feature = "serde-support")]
+    #[test]
+    fn parameterized_distance_serialization_test() {
+        let words = [
+            "book", "books", "boo", "boon", "cook", "cake", "cape", "cart",
+        ]
+        .map(String::from);
+
+        let mut dice = BkTree::new(DiceDistance { scale: 1000 });
+        dice.insert_all(words.clone());
+        let encoded = bincode::serialize(&dice).unwrap();
+        let decoded: BkTree<String, DiceDistance> = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded.dist.scale, 1000);
+        let found = decoded.find("bool".to_string(), 600);
+        assert!(found.iter().any(|(_, d)| *d > 100));
+        assert_eq!(found, dice.find("bool".to_string(), 600));
+
+        // Adjacent keys change which words are within a radius
+        let mut keyboard = BkTree::new(KeyboardLevenshtein::new([('k', 'z')]));
+        keyboard.insert_all(words);
+        let encoded = bincode::serialize(&keyboard).unwrap();
+        let decoded: BkTree<String, KeyboardLevenshtein> = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(
+            decoded.find("booz".to_string(), 1),
+            [(&"book".to_string(), 1)]
+        );
+        assert!(KeyboardLevenshtein::default().distance("booz", "book") > 1);
     }
 
     #[cfg(feature = "serde-support")]
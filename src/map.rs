@@ -0,0 +1,117 @@
+use crate::{BkTree, Distance, LevenshteinDistance};
+use std::ops::Index;
+
+/// Distance over key-value pairs which only looks at the keys
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[derive(Debug, Clone)]
+struct KeyDistance<D>(D);
+
+impl<K, V, D: Distance<K>> Distance<(K, V)> for KeyDistance<D> {
+    fn distance(&self, a: &(K, V), b: &(K, V)) -> isize {
+        self.0.distance(&a.0, &b.0)
+    }
+}
+
+/// A BK-tree associating a value with each key
+///
+/// ```rust
+/// use bktree::*;
+///
+/// let mut bk = BkMap::new(LevenshteinDistance);
+/// bk.insert("book", 1);
+/// bk.insert("boo", 2);
+///
+/// assert_eq!(bk.find(&"bo", 1), [(&"boo", &2, 1)]);
+/// ```
+#[cfg_attr(
+    feature = "serde-support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[derive(Clone)]
+pub struct BkMap<K, V, D = LevenshteinDistance> {
+    tree: BkTree<(K, V), KeyDistance<D>>,
+}
+
+impl<K, V, D> BkMap<K, V, D>
+where
+    D: Distance<K>,
+{
+    /// Create a new BK-map with a given distance function over keys
+    pub fn new(dist: D) -> Self {
+        Self {
+            tree: BkTree::new(KeyDistance(dist)),
+        }
+    }
+
+    /// Insert a new key-value pair in the BK-map
+    ///
+    /// If a key at distance 0 is already present, the existing entry is kept.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.tree.insert((key, value));
+    }
+
+    /// Get the value associated with a key at distance 0 of a given key
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.find(key, 0).into_iter().next().map(|(_, v, _)| v)
+    }
+
+    /// Find the closest keys to a given key present in the BK-map
+    ///
+    /// Returns triples of key references, value references and distances
+    pub fn find(&self, key: &K, max_dist: isize) -> Vec<(&K, &V, isize)> {
+        self.tree
+            .find_by(|(k, _)| self.tree.dist.0.distance(k, key), max_dist)
+            .into_iter()
+            .map(|((k, v), dist)| (k, v, dist))
+            .collect()
+    }
+
+    /// Find the closest keys to a given key present in the BK-map, to update their values
+    ///
+    /// Returns triples of key references, mutable value references and distances
+    pub fn find_mut(&mut self, key: &K, max_dist: isize) -> Vec<(&K, &mut V, isize)> {
+        self.tree
+            .find_by_mut(|dist, (k, _)| dist.0.distance(k, key), max_dist)
+            .into_iter()
+            .map(|((k, v), dist)| (&*k, v, dist))
+            .collect()
+    }
+
+    /// Find the closest keys to a given key, ranked by `distance - weight * priority` in
+    /// ascending order, the values being the priorities
+    ///
+    /// Pruning still uses the raw distance, the ranking only applies to the matches found
+    /// within `max_dist`.
+    pub fn find_ranked(&self, key: &K, max_dist: isize, weight: f64) -> Vec<(&K, &V, isize)>
+    where
+        V: Copy + Into<f64>,
+    {
+        let score = |v: &V, dist: isize| dist as f64 - weight * (*v).into();
+        let mut found = self.find(key, max_dist);
+        found.sort_by(|(_, a, a_dist), (_, b, b_dist)| {
+            score(a, *a_dist).total_cmp(&score(b, *b_dist))
+        });
+        found
+    }
+
+    /// Create an iterator over references of BK-map entries, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.tree.iter().map(|(k, v)| (k, v))
+    }
+}
+
+impl<K, V, D: Distance<K>> Index<&K> for BkMap<K, V, D> {
+    type Output = V;
+
+    /// Get the value associated with a key at distance 0 of a given key
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no such key in the BK-map.
+    fn index(&self, key: &K) -> &V {
+        self.get(key).expect("no key at distance 0 in the BK-map")
+    }
+}
@@ -0,0 +1,116 @@
+use crate::{
+    DistanceMut, Insert, Insertion, Iter, LevenshteinDistance, Node, DEFAULT_INDEX_THRESHOLD,
+};
+
+/// A BK-tree whose distance function needs mutable access to itself
+///
+/// Since computing a distance may mutate the distance function, searches take `&mut self`.
+///
+/// ```rust
+/// use bktree::*;
+///
+/// struct CountingLevenshtein(usize);
+///
+/// impl DistanceMut<&str> for CountingLevenshtein {
+///     fn distance(&mut self, a: &&str, b: &&str) -> isize {
+///         self.0 += 1;
+///         Distance::distance(&LevenshteinDistance, a, b)
+///     }
+/// }
+///
+/// let mut bk = BkTreeMut::new(CountingLevenshtein(0));
+/// bk.insert_all(vec!["book", "boo", "cake"]);
+/// assert_eq!(bk.find("bo", 1), [(&"boo", 1)]);
+/// assert!(bk.dist().0 > 0);
+/// ```
+pub struct BkTreeMut<T, D = LevenshteinDistance> {
+    root: Option<Node<T>>,
+    dist: D,
+}
+
+impl<T, D> BkTreeMut<T, D>
+where
+    D: DistanceMut<T>,
+{
+    /// Create a new BK-tree with a given mutable distance function
+    pub fn new(dist: D) -> Self {
+        Self { root: None, dist }
+    }
+
+    /// Get the distance function of the BK-tree
+    pub fn dist(&self) -> &D {
+        &self.dist
+    }
+
+    /// Insert every element from a given iterator in the BK-tree
+    pub fn insert_all<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for i in iter {
+            self.insert(i);
+        }
+    }
+
+    /// Insert a new element in the BK-tree
+    ///
+    /// Returns `false` if an element at distance 0 was already present, in which case the BK-tree
+    /// is left unchanged
+    pub fn insert(&mut self, val: T) -> bool {
+        let root = match self.root {
+            None => {
+                self.root = Some(Node::new(val));
+                return true;
+            }
+            Some(ref mut root) => root,
+        };
+        let dist = &mut self.dist;
+        let opts = Insert {
+            multiset: false,
+            threshold: DEFAULT_INDEX_THRESHOLD,
+            hint: None,
+            fallible: false,
+        };
+        match root.insert_node(val, |a, b| dist.distance(a, b), opts) {
+            Insertion::Added(..) => true,
+            Insertion::Present(_) => false,
+            Insertion::Failed(_) => unreachable!("infallible insertions do not fail"),
+        }
+    }
+
+    /// Find the closest elements to a given value present in the BK-tree
+    ///
+    /// Returns pairs of element references and distances
+    pub fn find(&mut self, val: T, max_dist: isize) -> Vec<(&T, isize)> {
+        let mut found = Vec::new();
+        let mut candidates: std::collections::VecDeque<&Node<T>> = self.root.iter().collect();
+        while let Some(n) = candidates.pop_front() {
+            let distance = self.dist.distance(&n.word, &val);
+            if distance <= max_dist {
+                found.push((&n.word, distance));
+            }
+
+            candidates.extend(
+                n.children
+                    .iter()
+                    .filter(|(arc, _)| (*arc - distance).abs() <= max_dist)
+                    .map(|(_, node)| node),
+            );
+        }
+        found
+    }
+
+    /// Count the elements of the BK-tree
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Check whether the BK-tree holds no element
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Iterate over the elements of the BK-tree, by reference
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            queue: self.root.iter().collect(),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::{BkTree, Distance, Node};
+
+/// A pool of workers able to run a batch of jobs concurrently
+///
+/// Implement it on top of the thread pool of your choice to use
+/// [`find_with_pool`](BkTree::find_with_pool). [`ScopedThreads`] is a simple implementation
+/// spawning scoped threads for every batch.
+pub trait Pool {
+    /// Run every job, possibly concurrently, returning their results in the order of the jobs
+    fn run<'a, R: Send + 'a>(&self, jobs: Vec<Box<dyn FnOnce() -> R + Send + 'a>>) -> Vec<R>;
+}
+
+/// A [`Pool`] splitting each batch of jobs between a given number of scoped threads
+#[derive(Debug, Clone)]
+pub struct ScopedThreads(pub usize);
+
+impl Pool for ScopedThreads {
+    fn run<'a, R: Send + 'a>(&self, jobs: Vec<Box<dyn FnOnce() -> R + Send + 'a>>) -> Vec<R> {
+        let chunk_size = jobs.len().div_ceil(self.0.max(1)).max(1);
+        let mut jobs = jobs.into_iter();
+        let chunks: Vec<Vec<_>> = std::iter::from_fn(|| {
+            let chunk: Vec<_> = jobs.by_ref().take(chunk_size).collect();
+            (!chunk.is_empty()).then_some(chunk)
+        })
+        .collect();
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = chunks
+                .into_iter()
+                .map(|chunk| {
+                    scope.spawn(move || chunk.into_iter().map(|job| job()).collect::<Vec<R>>())
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|e| std::panic::resume_unwind(e))
+                })
+                .collect()
+        })
+    }
+}
+
+impl<T, D> BkTree<T, D>
+where
+    D: Distance<T>,
+{
+    /// Find the closest elements to a given value present in the BK-tree, computing distances
+    /// on a pool of workers
+    ///
+    /// Whether a child needs to be visited depends on the distance to its parent, so distances
+    /// are computed one level of the tree at a time: the candidates of a level are evaluated
+    /// concurrently, and the next level is only known once they are all done. Parallelism is
+    /// therefore limited by the number of candidates per level, which is low near the root and
+    /// for small radii. This only pays off for distance functions much more expensive than
+    /// dispatching a job. Results are returned in the order [`find`](Self::find) would return
+    /// them.
+    pub fn find_with_pool<P: Pool>(&self, val: T, max_dist: isize, pool: &P) -> Vec<(&T, isize)>
+    where
+        T: Sync,
+        D: Sync,
+    {
+        let mut found = Vec::new();
+        let mut level: Vec<&Node<T>> = self.root.iter().collect();
+        while !level.is_empty() {
+            let jobs = level
+                .iter()
+                .map(|n| {
+                    let (dist, val) = (&self.dist, &val);
+                    Box::new(move || dist.distance(&n.word, val))
+                        as Box<dyn FnOnce() -> isize + Send + '_>
+                })
+                .collect();
+            let distances = pool.run(jobs);
+
+            let mut next = Vec::new();
+            for (n, distance) in level.into_iter().zip(distances) {
+                if distance <= max_dist {
+                    found.push((&n.word, distance));
+                }
+                next.extend(
+                    n.children
+                        .iter()
+                        .filter(|(arc, _)| (*arc - distance).abs() <= max_dist)
+                        .map(|(_, node)| node),
+                );
+            }
+            level = next;
+        }
+        found
+    }
+}
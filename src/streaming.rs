@@ -0,0 +1,56 @@
+use crate::Distance;
+
+/// A distance function comparing two values given as streams of tokens
+///
+/// Streams may be cloned to be traversed several times, which lets metrics work on values too
+/// large to be materialized, as long as their tokens can be regenerated.
+pub trait StreamingDistance<Tok> {
+    fn distance_streams<A, B>(&self, a: A, b: B) -> isize
+    where
+        A: Iterator<Item = Tok> + Clone,
+        B: Iterator<Item = Tok> + Clone;
+}
+
+/// Adapter using a string distance function as a [`StreamingDistance`] over characters, by
+/// collecting both streams before comparing them
+#[derive(Debug, Clone)]
+pub struct Materialized<D>(pub D);
+
+impl<D: Distance<str>> StreamingDistance<char> for Materialized<D> {
+    fn distance_streams<A, B>(&self, a: A, b: B) -> isize
+    where
+        A: Iterator<Item = char> + Clone,
+        B: Iterator<Item = char> + Clone,
+    {
+        self.0
+            .distance(&a.collect::<String>(), &b.collect::<String>())
+    }
+}
+
+impl<T: ?Sized, D: Distance<T>> Distance<T> for Materialized<D> {
+    fn distance(&self, a: &T, b: &T) -> isize {
+        self.0.distance(a, b)
+    }
+}
+
+/// Adapter using a [`StreamingDistance`] over characters as a string distance function, so it
+/// can back a BK-tree of strings
+#[derive(Debug, Clone)]
+pub struct Streamed<S>(pub S);
+
+impl<S: StreamingDistance<char>> StreamingDistance<char> for Streamed<S> {
+    fn distance_streams<A, B>(&self, a: A, b: B) -> isize
+    where
+        A: Iterator<Item = char> + Clone,
+        B: Iterator<Item = char> + Clone,
+    {
+        self.0.distance_streams(a, b)
+    }
+}
+
+impl<T: AsRef<str> + ?Sized, S: StreamingDistance<char>> Distance<T> for Streamed<S> {
+    fn distance(&self, a: &T, b: &T) -> isize {
+        self.0
+            .distance_streams(a.as_ref().chars(), b.as_ref().chars())
+    }
+}
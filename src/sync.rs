@@ -0,0 +1,87 @@
+use crate::{BkTree, Distance, LevenshteinDistance};
+use std::sync::RwLock;
+
+/// A BK-tree shared between threads behind a reader-writer lock
+///
+/// Searches take the read lock and may run concurrently, insertions take the write lock. Since
+/// references cannot outlive the lock guard, searches return owned copies of the elements.
+///
+/// ```rust
+/// use bktree::*;
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let bk = Arc::new(SyncBkTree::new(LevenshteinDistance));
+/// bk.insert("book");
+///
+/// let writer = Arc::clone(&bk);
+/// thread::spawn(move || writer.insert("boon")).join().unwrap();
+///
+/// assert_eq!(bk.find("boo", 1), [("book", 1), ("boon", 1)]);
+/// ```
+pub struct SyncBkTree<T, D = LevenshteinDistance> {
+    tree: RwLock<BkTree<T, D>>,
+}
+
+impl<T, D> SyncBkTree<T, D>
+where
+    D: Distance<T>,
+{
+    /// Create a new thread-safe BK-tree with a given distance function
+    pub fn new(dist: D) -> Self {
+        Self::from(BkTree::new(dist))
+    }
+
+    /// Insert a new element in the BK-tree, waiting for concurrent searches to finish
+    ///
+    /// Returns `false` if an element at distance 0 was already present, in which case the BK-tree
+    /// is left unchanged
+    pub fn insert(&self, val: T) -> bool {
+        self.tree
+            .write()
+            .expect("SyncBkTree lock poisoned")
+            .insert(val)
+    }
+
+    /// Find the closest elements to a given value present in the BK-tree
+    ///
+    /// Returns pairs of cloned elements and distances
+    pub fn find(&self, val: T, max_dist: isize) -> Vec<(T, isize)>
+    where
+        T: Clone,
+    {
+        self.tree
+            .read()
+            .expect("SyncBkTree lock poisoned")
+            .find(val, max_dist)
+            .into_iter()
+            .map(|(found, dist)| (found.clone(), dist))
+            .collect()
+    }
+
+    /// Count the elements of the BK-tree
+    pub fn len(&self) -> usize {
+        self.tree.read().expect("SyncBkTree lock poisoned").len()
+    }
+
+    /// Check whether the BK-tree holds no element
+    pub fn is_empty(&self) -> bool {
+        self.tree
+            .read()
+            .expect("SyncBkTree lock poisoned")
+            .is_empty()
+    }
+
+    /// Unwrap the underlying BK-tree
+    pub fn into_inner(self) -> BkTree<T, D> {
+        self.tree.into_inner().expect("SyncBkTree lock poisoned")
+    }
+}
+
+impl<T, D> From<BkTree<T, D>> for SyncBkTree<T, D> {
+    fn from(tree: BkTree<T, D>) -> Self {
+        Self {
+            tree: RwLock::new(tree),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use crate::rng::SplitMix64;
+use crate::{BkTree, Distance};
+use std::ops::Range;
+
+/// Generate `count` random lowercase ASCII words with lengths in `len_range`
+///
+/// The same seed always yields the same words. Panics if `len_range` is empty.
+pub fn random_words(seed: u64, count: usize, len_range: Range<usize>) -> Vec<String> {
+    let mut rng = SplitMix64(seed);
+    let span = (len_range.end - len_range.start) as u64;
+    (0..count)
+        .map(|_| {
+            let len = len_range.start + rng.below(span) as usize;
+            (0..len)
+                .map(|_| (b'a' + rng.below(26) as u8) as char)
+                .collect()
+        })
+        .collect()
+}
+
+/// Generate `count` random integers
+///
+/// The same seed always yields the same integers.
+pub fn random_u64s(seed: u64, count: usize) -> Vec<u64> {
+    let mut rng = SplitMix64(seed);
+    (0..count).map(|_| rng.next()).collect()
+}
+
+impl<T, D> BkTree<T, D>
+where
+    D: Distance<T>,
+{
+    /// Compute the fraction of the matches of [`find`](BkTree::find) which
+    /// [`find_approx`](BkTree::find_approx) finds with a given slack
+    ///
+    /// Returns 1.0 when there is no match at all.
+    pub fn recall_vs_exact(&self, val: T, max_dist: isize, slack: isize) -> f64 {
+        let distance_to = |word: &T| self.dist.distance(word, &val);
+        let exact = self.find_by_pruning(distance_to, max_dist, max_dist).len();
+        let approx = self
+            .find_by_pruning(distance_to, max_dist, max_dist - slack)
+            .len();
+        if exact == 0 {
+            1.0
+        } else {
+            approx as f64 / exact as f64
+        }
+    }
+}